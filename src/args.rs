@@ -8,6 +8,11 @@ use std::path::{Path, PathBuf};
 
 pub struct Args {
 	pub config: Option<PathBuf>,
+	pub waybar: Option<String>,
+	pub preview: bool,
+	pub output: Option<String>,
+	pub metrics_address: Option<String>,
+	pub metrics_file: Option<PathBuf>,
 }
 
 pub fn collect() -> Args {
@@ -22,12 +27,53 @@ pub fn collect() -> Args {
 				.help("Config file to use.")
 				.takes_value(true),
 		)
+		.arg(
+			Arg::with_name("waybar")
+				.long("waybar")
+				.value_name("BLOCK")
+				.help("Run only the named block and output JSON for a waybar custom module.")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("preview")
+				.long("preview")
+				.help("Preview the bar in the terminal.")
+				.conflicts_with("waybar"),
+		)
+		.arg(
+			Arg::with_name("output")
+				.short("o")
+				.long("output")
+				.help("Output format to publish blocks in.")
+				.possible_values(&["i3bar", "jsonl"])
+				.takes_value(true)
+				.conflicts_with_all(&["waybar", "preview"]),
+		)
+		.arg(
+			Arg::with_name("metrics-address")
+				.long("metrics-address")
+				.value_name("ADDRESS")
+				.help("Serve Prometheus metrics of block values over HTTP (e.g. 127.0.0.1:9899).")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("metrics-file")
+				.long("metrics-file")
+				.value_name("PATH")
+				.help("Write Prometheus metrics of block values to a file.")
+				.takes_value(true),
+		)
 		.get_matches();
 
 	Args {
 		config: matches
 			.value_of("config")
 			.map_or_else(default_config, |x| Some(Path::new(x).to_path_buf())),
+		waybar: matches.value_of("waybar").map(String::from),
+		preview: matches.is_present("preview"),
+		output: matches.value_of("output").map(String::from),
+		metrics_address: matches.value_of("metrics-address").map(String::from),
+		metrics_file: matches.value_of("metrics-file").map(PathBuf::from),
 	}
 }
 
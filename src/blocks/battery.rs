@@ -7,6 +7,25 @@
 //!
 //! Use this block to get battery monitoring in the status bar.
 //!
+//! By default all batteries found in `/sys/class/power_supply` are monitored
+//! and their combined charge and time remaining are shown. Batteries may
+//! report their charge (`charge_now`/`charge_full`), their energy
+//! (`energy_now`/`energy_full`) or only a percentage (`capacity`), and
+//! whichever is available is used. When all batteries report their current rate
+//! of charge (`current_now`/`power_now`) it is used to estimate the time
+//! remaining, otherwise the time remaining is estimated from how quickly the
+//! charge changes. The rates learned are saved under
+//! `$XDG_STATE_HOME/rs-blocks/` (at most once a minute and on shutdown) so that
+//! an estimate can be shown straight away after a restart, which then converges
+//! to the live rate.
+//!
+//! Power adapters are also monitored so that when the batteries aren't charging
+//! the block can show whether they're unplugged or being held at a charge
+//! threshold.
+//!
+//! NB multiple batteries can only be monitored together if they report in the
+//! same units, i.e. all by charge, all by energy or all by capacity.
+//!
 //! Typical configuration:
 //!
 //! ```toml
@@ -17,22 +36,78 @@
 //!
 //! - `name`: Name of the block (must be unique)
 //! - `period`: Default update period in seconds (extra updates may occur on
-//!    event changes etc)
+//!   event changes etc)
 //! - `alpha`: Weight for the exponential moving average of value updates
+//! - `format`: Format of the block's text. Available placeholders are
+//!   `{symbol}`, `{percentage}`, `{remaining}`, `{status}`, `{source}` (one of
+//!   `mains`, `usb-pd` or `battery`) and `{threshold}` (the charge control end
+//!   threshold, if set). The same values are available for each battery
+//!   prefixed by the battery's name in lower case, e.g. `{bat0_percentage}` or
+//!   `{bat1_status}`
+//! - `format_detailed`: Format of the block's text after it's left clicked
+//!   (clicking again switches back). As well as the placeholders of `format`,
+//!   `{health_percentage}` (maximum capacity as a percentage of the design
+//!   capacity) and `{cycle_count}` are available, and for each battery
+//!   `{bat0_health_percentage}`, `{bat0_cycle_count}`, `{bat0_technology}`,
+//!   `{bat0_model_name}` and `{bat0_manufacturer}` etc (when the kernel
+//!   provides them). Defaults to showing the health and cycle count, leaving
+//!   out whichever the kernel doesn't provide (or the same as `format` if it
+//!   provides neither)
+//! - `batteries`: List of batteries to monitor, given either as names in
+//!   `/sys/class/power_supply` (e.g. `["BAT0", "BAT1"]`) or as paths to their
+//!   directories (defaults to all `BAT*` devices)
+//! - `adapters`: List of power adapters to monitor, given in the same way as
+//!   `batteries` (defaults to all `AC*`, `ADP*` and `ucsi-*` devices)
+//! - `warning`: Percentage of charge at or below which the block is shown as a
+//!   warning while discharging
+//! - `critical`: Percentage of charge at or below which the block is shown as
+//!   critical (and marked urgent) while discharging
+//! - `on_below`: List of actions to run when the charge falls to or below a
+//!   percentage while discharging, given as tables with `percent` and `run` (a
+//!   shell command). Each action runs once per discharge and is re-armed when
+//!   the battery starts charging
 //! - `path_to_charge_now`: Path to file containing current charge (usually
-//!    something like `/sys/class/power_supply/BAT0/charge_now`)
+//!   something like `/sys/class/power_supply/BAT0/charge_now`). If this or any
+//!   of the other `path_to_*` options are given then only a single battery is
+//!   monitored and `batteries` is ignored
 //! - `path_to_charge_full`: Path to file containing charge value when full
-//!    (usually something like `/sys/class/power_supply/BAT0/charge_full`)
+//!   (usually something like `/sys/class/power_supply/BAT0/charge_full`)
 //! - `path_to_status`: Path to file containing battery status (usually
-//!    something like `/sys/class/power_supply/BAT0/status`)
+//!   something like `/sys/class/power_supply/BAT0/status`)
+//!
+//! For example, to show the charge of two batteries separately:
+//!
+//! ```toml
+//! [battery]
+//! format = "{symbol} {bat0_percentage}% + {bat1_percentage}% ({remaining})"
+//! ```
+//!
+//! Or to be notified when the battery is low and suspend when it's nearly
+//! empty:
+//!
+//! ```toml
+//! [battery]
+//! on_below = [
+//!   {percent = 15, run = "notify-send 'Battery low'"},
+//!   {percent = 5, run = "systemctl suspend"},
+//! ]
+//! ```
 
-use crate::blocks::{Block, Configure, Message as BlockMessage, Sender, ValidatedPath};
-use crate::{ema, utils};
+use crate::blocks::{
+	Block, Configure, Message as BlockMessage, Sender, State, ValidatedPath, Value, Values,
+};
+use crate::{click, ema, shutdown, state, utils};
 use anyhow::Context;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+const SAVE_PERIOD: Duration = Duration::from_secs(60);
 
 #[derive(Configure, Deserialize)]
 pub struct Battery {
@@ -42,12 +117,22 @@ pub struct Battery {
 	period: f32,
 	#[serde(default = "default_alpha")]
 	alpha: f32,
-	#[serde(default = "default_path_to_charge_now")]
-	path_to_charge_now: ValidatedPath,
-	#[serde(default = "default_path_to_charge_full")]
-	path_to_charge_full: ValidatedPath,
-	#[serde(default = "default_path_to_status")]
-	path_to_status: ValidatedPath,
+	#[serde(default = "default_format")]
+	format: String,
+	format_detailed: Option<String>,
+	#[serde(default)]
+	batteries: Vec<String>,
+	#[serde(default)]
+	adapters: Vec<String>,
+	#[serde(default = "default_warning")]
+	warning: f32,
+	#[serde(default = "default_critical")]
+	critical: f32,
+	#[serde(default)]
+	on_below: Vec<Action>,
+	path_to_charge_now: Option<ValidatedPath>,
+	path_to_charge_full: Option<ValidatedPath>,
+	path_to_status: Option<ValidatedPath>,
 }
 
 fn default_name() -> String {
@@ -62,97 +147,621 @@ fn default_alpha() -> f32 {
 	0.8
 }
 
-fn default_path_to_charge_now() -> ValidatedPath {
-	ValidatedPath("/sys/class/power_supply/BAT0/charge_now".to_string())
+fn default_format() -> String {
+	"{symbol} {percentage}% ({remaining})".to_string()
 }
 
-fn default_path_to_charge_full() -> ValidatedPath {
-	ValidatedPath("/sys/class/power_supply/BAT0/charge_full".to_string())
+/// Get the default detailed format, only including the details in `values`.
+fn default_format_detailed(values: &Values, format: &str) -> String {
+	let parts: Vec<&str> = [
+		("health_fraction", "{health_percentage}% health"),
+		("cycle_count", "{cycle_count} cycles"),
+	]
+	.iter()
+	.filter(|(key, _)| values.contains_key(*key))
+	.map(|(_, part)| *part)
+	.collect();
+	if parts.is_empty() {
+		format.to_string()
+	} else {
+		format!("{{symbol}} {}", parts.join(", "))
+	}
+}
+
+fn default_warning() -> f32 {
+	25.0
+}
+
+fn default_critical() -> f32 {
+	10.0
+}
+
+fn default_path_to_charge_now() -> String {
+	format!("{}/BAT0/charge_now", POWER_SUPPLY_DIR)
 }
 
-fn default_path_to_status() -> ValidatedPath {
-	ValidatedPath("/sys/class/power_supply/BAT0/status".to_string())
+fn default_path_to_charge_full() -> String {
+	format!("{}/BAT0/charge_full", POWER_SUPPLY_DIR)
+}
+
+fn default_path_to_status() -> String {
+	format!("{}/BAT0/status", POWER_SUPPLY_DIR)
+}
+
+impl Battery {
+	/// Get the batteries to monitor.
+	fn supplies(&self) -> anyhow::Result<Vec<Supply>> {
+		if self.path_to_charge_now.is_some()
+			|| self.path_to_charge_full.is_some()
+			|| self.path_to_status.is_some()
+		{
+			let path_to_charge_now = self
+				.path_to_charge_now
+				.as_ref()
+				.map_or_else(default_path_to_charge_now, |x| x.0.clone());
+			let path_to_charge_full = self
+				.path_to_charge_full
+				.as_ref()
+				.map_or_else(default_path_to_charge_full, |x| x.0.clone());
+			let path_to_status = self
+				.path_to_status
+				.as_ref()
+				.map_or_else(default_path_to_status, |x| x.0.clone());
+			let name = Path::new(&path_to_charge_now)
+				.parent()
+				.and_then(|x| x.file_name())
+				.map_or_else(
+					|| "battery".to_string(),
+					|x| x.to_string_lossy().to_string(),
+				);
+			let (max, design) = get_max_capacity(&path_to_charge_full)?;
+			let info = Path::new(&path_to_charge_full)
+				.parent()
+				.map(get_info)
+				.unwrap_or_default();
+			return Ok(vec![Supply {
+				name,
+				max,
+				design,
+				info,
+				unit: Unit::Charge,
+				path_to_now: path_to_charge_now,
+				path_to_rate: None,
+				path_to_status,
+				path_to_threshold: None,
+			}]);
+		}
+
+		let dirs = if self.batteries.is_empty() {
+			let dirs = discover(&["BAT"])?;
+			if dirs.is_empty() {
+				anyhow::bail!("No batteries found in '{}'", POWER_SUPPLY_DIR);
+			}
+			dirs
+		} else {
+			self.batteries
+				.iter()
+				.map(|x| Path::new(POWER_SUPPLY_DIR).join(x))
+				.collect()
+		};
+		let supplies: Vec<Supply> = dirs
+			.iter()
+			.map(|x| Supply::new(x))
+			.collect::<Result<_, _>>()?;
+		check_units(&supplies)?;
+		Ok(supplies)
+	}
+
+	/// Get the power adapters to monitor.
+	fn adapters(&self) -> anyhow::Result<Vec<Adapter>> {
+		let dirs = if self.adapters.is_empty() {
+			discover(&["AC", "ADP", "ucsi-"])?
+		} else {
+			self.adapters
+				.iter()
+				.map(|x| Path::new(POWER_SUPPLY_DIR).join(x))
+				.collect()
+		};
+		dirs.iter().map(|x| Adapter::new(x)).collect()
+	}
 }
 
 impl Sender for Battery {
 	fn add_sender(&self, channel: crossbeam_channel::Sender<BlockMessage>) -> anyhow::Result<()> {
 		let name = self.get_name();
-		let max = get_max_capacity(&self.path_to_charge_full.0)?;
+		let format = self.format.clone();
+		let format_detailed = self.format_detailed.clone();
+		let mut detailed = false;
+		let (warning, critical) = (self.warning, self.critical);
+		let actions = self.on_below.clone();
+		let mut armed = vec![true; actions.len()];
+		let supplies = self.supplies()?;
+		let adapters = self.adapters()?;
+		let max: f32 = supplies.iter().map(|x| x.max).sum();
 		let (tx, rx) = crossbeam_channel::unbounded();
 		let mut sremain = "...".to_string();
+		let mut minutes = None;
 		let mut last_status_change = 0;
-		let mut remaining = ema::Ema::new(self.alpha);
-		let (mut current_charge, mut current_status) = initialise(
-			&self.path_to_charge_now.0,
-			&self.path_to_status.0,
-			self.period,
-			tx,
-		)?;
+		let alpha = self.alpha;
+		let mut remaining = ema::Ema::new(alpha);
+		let learned: Arc<Mutex<Learned>> =
+			Arc::new(Mutex::new(state::load(&name).unwrap_or_default()));
+		{
+			let (name, learned) = (name.clone(), learned.clone());
+			shutdown::register(move || save_learned(&name, &learned.lock().unwrap()));
+		}
+		let mut saved = Instant::now();
+		let mut unsaved = false;
+		let mut readings = initialise(&supplies, &adapters, self.period, tx)?;
+		let mut current_charge: f32 = readings.charges.iter().sum();
+		let mut current_status = combine_statuses(&readings.statuses);
 		let mut then = Instant::now();
-		let mut fraction = (current_charge / max).min(1.0);
-		let mut block = Block::new(name.clone(), true);
-		let symbol = get_symbol(current_status, fraction);
+		let clicks = click::listen(&name);
+		let mut block = Block::new(name, true);
 
-		if current_status == Status::Full {
-			block.full_text = Some(create_full_text(symbol, fraction, "Full"));
-			channel.send((name.clone(), block.to_string())).unwrap();
+		if let Some(estimate) =
+			kernel_estimate(current_status, current_charge, max, &readings.rates).or_else(|| {
+				learned
+					.lock()
+					.unwrap()
+					.estimate(current_status, current_charge, max)
+			}) {
+			let estimate = remaining.push(estimate);
+			minutes = Some(estimate);
+			sremain = minutes_to_string(estimate);
 		}
 
 		thread::spawn(move || loop {
-			let message = rx.recv().unwrap();
-			log::debug!("{:?}", message);
+			if unsaved && saved.elapsed() >= SAVE_PERIOD {
+				save_learned(&block.name, &learned.lock().unwrap());
+				saved = Instant::now();
+				unsaved = false;
+			}
+
+			let fraction = get_fraction(&supplies, &readings.charges);
+			let source = get_source(&adapters, &readings.online);
+			match current_status {
+				Status::Full => sremain = "Full".to_string(),
+				Status::NotCharging => {
+					if let Some(reason) = not_charging_reason(source, &readings.thresholds) {
+						sremain = reason;
+					}
+				}
+				_ => {}
+			}
+			for i in due_actions(&actions, &mut armed, current_status, fraction * 100.0) {
+				run_action(&actions[i]);
+			}
+			let values = get_values(&supplies, &readings, current_status, source, minutes);
+			let symbol = get_symbol(current_status, fraction);
+			let state = get_state(current_status, fraction * 100.0, warning, critical);
+			let format = match (detailed, &format_detailed) {
+				(false, _) => format.clone(),
+				(true, Some(format_detailed)) => format_detailed.clone(),
+				(true, None) => default_format_detailed(&values, &format),
+			};
+			block.full_text = Some(create_full_text(&format, &values, symbol, &sremain));
+			channel
+				.send(create_message(&block, state, fraction, values))
+				.unwrap();
+
+			let (i, message) = crossbeam_channel::select! {
+				recv(rx) -> message => message.unwrap(),
+				recv(clicks) -> click => {
+					detailed = toggle_detailed(detailed, click.unwrap().button);
+					continue;
+				}
+			};
+			let source = match message {
+				Message::Online(_) => &adapters[i].name,
+				_ => &supplies[i].name,
+			};
+			log::debug!("{}: {:?}", source, message);
 			let now = Instant::now();
 
 			match message {
 				Message::Charge(charge) => {
-					sremain = if last_status_change == 0 {
-						remaining.reset();
-						"...".to_string()
-					} else {
-						let elapsed = now.duration_since(then).as_secs() as f32 / 60.0;
-						let gap = match current_status {
-							Status::Charging => max - charge,
-							Status::Discharging => charge,
-							Status::Full => 0.0,
-							Status::NotCharging => charge,
-							Status::Unknown => charge,
+					readings.charges[i] = charge;
+					let charge = readings.charges.iter().sum();
+					// Fall back to estimating the rate from changes in charge when the
+					// kernel doesn't report it.
+					if kernel_rate(&readings.rates).is_none() {
+						sremain = if last_status_change == 0 {
+							remaining.reset();
+							minutes = learned
+								.lock()
+								.unwrap()
+								.estimate(current_status, charge, max)
+								.map(|x| remaining.push(x));
+							minutes.map_or_else(|| "...".to_string(), minutes_to_string)
+						} else {
+							let elapsed = now.duration_since(then).as_secs_f32() / 60.0;
+							let gap = get_gap(current_status, charge, max);
+							if charge == current_charge {
+								continue;
+							}
+							let rate = (charge - current_charge).abs() / elapsed;
+							log::info!("rate = {}", rate);
+							learned.lock().unwrap().update(current_status, rate, alpha);
+							unsaved = true;
+							let estimate = remaining.push(gap / rate);
+							minutes = Some(estimate);
+							minutes_to_string(estimate)
 						};
-						if charge == current_charge {
-							continue;
-						}
-						let rate = (charge - current_charge).abs() / elapsed;
-						log::info!("rate = {}", rate);
-						minutes_to_string(remaining.push(gap / rate))
-					};
+					}
 
 					then = now;
 					current_charge = charge;
 					last_status_change += 1;
-					fraction = (current_charge / max).min(1.0);
+				}
+				Message::Rate(rate) => {
+					readings.rates[i] = rate;
+					if let Some(estimate) =
+						kernel_estimate(current_status, current_charge, max, &readings.rates)
+					{
+						let rate = kernel_rate(&readings.rates).unwrap() / 60.0;
+						learned.lock().unwrap().update(current_status, rate, alpha);
+						unsaved = true;
+						let estimate = remaining.push(estimate);
+						minutes = Some(estimate);
+						sremain = minutes_to_string(estimate);
+					}
 				}
 				Message::Status(status) => {
+					readings.statuses[i] = status;
+					let status = combine_statuses(&readings.statuses);
 					if status != current_status {
 						last_status_change = 0;
 						current_status = status;
-						sremain = "...".to_string();
+						remaining.reset();
+						minutes = kernel_estimate(status, current_charge, max, &readings.rates)
+							.or_else(|| {
+								learned
+									.lock()
+									.unwrap()
+									.estimate(status, current_charge, max)
+							})
+							.map(|x| remaining.push(x));
+						sremain = minutes.map_or_else(|| "...".to_string(), minutes_to_string);
 					}
 				}
+				Message::Threshold(threshold) => readings.thresholds[i] = threshold,
+				Message::Online(online) => readings.online[i] = online,
 			}
-			if current_status == Status::Full {
-				sremain = "Full".to_string();
-			}
-			let symbol = get_symbol(current_status, fraction);
-			block.full_text = Some(create_full_text(symbol, fraction, &sremain));
-			channel.send((name.clone(), block.to_string())).unwrap();
 		});
 
 		Ok(())
 	}
 }
 
-fn get_max_capacity(path: &str) -> anyhow::Result<f32> {
-	let contents = fs::read_to_string(&path).context(format!("Could not read path '{}'", path))?;
-	utils::str_to_f32(&contents).context(format!("Could not parse contents of '{}'", path))
+/// Rates of charge and discharge (per minute) learned while running, which are
+/// persisted so that the time remaining can be estimated before there's any
+/// live data.
+#[derive(Default, Serialize, Deserialize)]
+struct Learned {
+	charge_rate: Option<f32>,
+	discharge_rate: Option<f32>,
+}
+
+impl Learned {
+	fn rate(&self, status: Status) -> Option<f32> {
+		match status {
+			Status::Charging => self.charge_rate,
+			Status::Discharging => self.discharge_rate,
+			_ => None,
+		}
+	}
+
+	/// Update the rate for the given status with a new measurement, smoothed
+	/// with weight `alpha`.
+	fn update(&mut self, status: Status, rate: f32, alpha: f32) {
+		let learned = match status {
+			Status::Charging => &mut self.charge_rate,
+			Status::Discharging => &mut self.discharge_rate,
+			_ => return,
+		};
+		*learned = Some(learned.map_or(rate, |x| alpha * x + (1.0 - alpha) * rate));
+	}
+
+	/// Estimate the minutes remaining from the learned rate, if there is one.
+	fn estimate(&self, status: Status, charge: f32, max: f32) -> Option<f32> {
+		self.rate(status)
+			.map(|rate| get_gap(status, charge, max) / rate)
+	}
+}
+
+/// Save the learned rates of the block with the given name.
+fn save_learned(name: &str, learned: &Learned) {
+	if let Err(e) = state::save(name, learned) {
+		log::warn!("Could not save battery state: {:?}", e);
+	}
+}
+
+/// A command to run when the charge falls to or below `percent`.
+#[derive(Clone, Deserialize)]
+struct Action {
+	percent: f32,
+	run: String,
+}
+
+/// Get the indices of the actions which are due to run, disarming them so they
+/// only run once. All actions are re-armed when the battery is charging.
+fn due_actions(
+	actions: &[Action],
+	armed: &mut [bool],
+	status: Status,
+	percentage: f32,
+) -> Vec<usize> {
+	match status {
+		Status::Charging | Status::Full => {
+			armed.iter_mut().for_each(|x| *x = true);
+			Vec::new()
+		}
+		Status::Discharging => actions
+			.iter()
+			.zip(armed.iter_mut())
+			.enumerate()
+			.filter(|(_, (action, armed))| **armed && percentage <= action.percent)
+			.map(|(i, (_, armed))| {
+				*armed = false;
+				i
+			})
+			.collect(),
+		_ => Vec::new(),
+	}
+}
+
+/// Run an action's command in the background.
+fn run_action(action: &Action) {
+	log::info!("Running '{}' at {}%", action.run, action.percent);
+	let run = action.run.clone();
+	thread::spawn(
+		move || match Command::new("sh").args(["-c", &run]).status() {
+			Ok(status) if !status.success() => log::warn!("'{}' failed with {}", run, status),
+			Err(e) => log::warn!("Could not run '{}': {}", run, e),
+			_ => {}
+		},
+	);
+}
+
+/// The kernel files of a single battery. Depending on what the battery
+/// provides, `path_to_now` contains either its charge, energy or capacity (in
+/// which case `max` is 100) and `path_to_rate` its current or power. `design`
+/// is the maximum when new and `info` any other details the kernel gives about
+/// the battery (e.g. `cycle_count`).
+struct Supply {
+	name: String,
+	max: f32,
+	design: Option<f32>,
+	info: Values,
+	unit: Unit,
+	path_to_now: String,
+	path_to_rate: Option<String>,
+	path_to_status: String,
+	path_to_threshold: Option<String>,
+}
+
+impl Supply {
+	/// Create a supply from a battery's directory (e.g.
+	/// `/sys/class/power_supply/BAT0`).
+	fn new(dir: &Path) -> anyhow::Result<Supply> {
+		let path = |file: &str| {
+			let path = dir.join(file);
+			if path.exists() {
+				Some(path.to_string_lossy().to_string())
+			} else {
+				None
+			}
+		};
+
+		let (unit, path_to_now, (max, design), path_to_rate) =
+			if let (Some(now), Some(full)) = (path("charge_now"), path("charge_full")) {
+				(
+					Unit::Charge,
+					now,
+					get_max_capacity(&full)?,
+					path("current_now"),
+				)
+			} else if let (Some(now), Some(full)) = (path("energy_now"), path("energy_full")) {
+				(
+					Unit::Energy,
+					now,
+					get_max_capacity(&full)?,
+					path("power_now"),
+				)
+			} else if let Some(capacity) = path("capacity") {
+				(Unit::Capacity, capacity, (100.0, None), None)
+			} else {
+				anyhow::bail!(
+					"Battery '{}' has no charge, energy or capacity files",
+					dir.display()
+				)
+			};
+
+		Ok(Supply {
+			name: dir.file_name().map_or_else(
+				|| "battery".to_string(),
+				|x| x.to_string_lossy().to_string(),
+			),
+			max,
+			design,
+			info: get_info(dir),
+			unit,
+			path_to_now,
+			path_to_rate,
+			path_to_status: path("status")
+				.context(format!("Battery '{}' has no status file", dir.display()))?,
+			path_to_threshold: path("charge_control_end_threshold"),
+		})
+	}
+}
+
+/// What a battery reports its charge as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unit {
+	Charge,
+	Energy,
+	Capacity,
+}
+
+impl Unit {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Unit::Charge => "charge",
+			Unit::Energy => "energy",
+			Unit::Capacity => "capacity",
+		}
+	}
+}
+
+/// Check that all batteries report in the same units, since their charges and
+/// rates are summed.
+fn check_units(supplies: &[Supply]) -> anyhow::Result<()> {
+	let first = match supplies.first() {
+		Some(first) => first,
+		None => return Ok(()),
+	};
+	if let Some(other) = supplies.iter().find(|x| x.unit != first.unit) {
+		anyhow::bail!(
+			"Battery '{}' reports its {} but '{}' reports its {}, monitor them in separate blocks",
+			first.name,
+			first.unit.as_str(),
+			other.name,
+			other.unit.as_str()
+		);
+	}
+	Ok(())
+}
+
+/// Where power is currently coming from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Source {
+	Mains,
+	Usb,
+	Battery,
+}
+
+impl Source {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Source::Mains => "mains",
+			Source::Usb => "usb-pd",
+			Source::Battery => "battery",
+		}
+	}
+}
+
+/// The kernel files of a power adapter, either a mains adapter (`AC*` or
+/// `ADP*`) or a USB-C port (`ucsi-*`).
+struct Adapter {
+	name: String,
+	source: Source,
+	path_to_online: String,
+}
+
+impl Adapter {
+	/// Create an adapter from its directory (e.g. `/sys/class/power_supply/AC`).
+	fn new(dir: &Path) -> anyhow::Result<Adapter> {
+		let path_to_online = dir.join("online");
+		if !path_to_online.exists() {
+			anyhow::bail!("Adapter '{}' has no online file", dir.display());
+		}
+		let kind = fs::read_to_string(dir.join("type")).unwrap_or_default();
+		let name = dir.file_name().unwrap_or_default().to_string_lossy();
+		let source = if kind.trim() == "USB" || name.starts_with("ucsi-") {
+			Source::Usb
+		} else {
+			Source::Mains
+		};
+		Ok(Adapter {
+			name: name.to_string(),
+			source,
+			path_to_online: path_to_online.to_string_lossy().to_string(),
+		})
+	}
+}
+
+/// Get where power is currently coming from, if there are any adapters to tell.
+fn get_source(adapters: &[Adapter], online: &[bool]) -> Option<Source> {
+	if adapters.is_empty() {
+		return None;
+	}
+	let is_online = |source| {
+		adapters
+			.iter()
+			.zip(online)
+			.any(|(adapter, online)| adapter.source == source && *online)
+	};
+	Some(if is_online(Source::Mains) {
+		Source::Mains
+	} else if is_online(Source::Usb) {
+		Source::Usb
+	} else {
+		Source::Battery
+	})
+}
+
+/// Explain why the batteries aren't charging, distinguishing being held at a
+/// charge threshold from being unplugged.
+fn not_charging_reason(source: Option<Source>, thresholds: &[Option<f32>]) -> Option<String> {
+	match (source?, thresholds.iter().flatten().next()) {
+		(Source::Battery, _) => Some("Unplugged".to_string()),
+		(_, Some(threshold)) => Some(format!("Held at {:.0}%", threshold)),
+		(_, None) => Some("Plugged in".to_string()),
+	}
+}
+
+/// Find all power supplies in the power supply directory whose names start with
+/// any of `prefixes`.
+fn discover(prefixes: &[&str]) -> anyhow::Result<Vec<PathBuf>> {
+	let entries = fs::read_dir(POWER_SUPPLY_DIR)
+		.context(format!("Could not read directory '{}'", POWER_SUPPLY_DIR))?;
+	let mut dirs: Vec<PathBuf> = entries
+		.filter_map(|x| x.ok())
+		.filter(|x| {
+			let name = x.file_name().to_string_lossy().to_string();
+			prefixes.iter().any(|prefix| name.starts_with(prefix))
+		})
+		.map(|x| x.path())
+		.collect();
+	dirs.sort();
+	Ok(dirs)
+}
+
+/// Get the maximum capacity from `path` (e.g. `charge_full`) along with the
+/// design capacity from its sibling (e.g. `charge_full_design`), if available.
+fn get_max_capacity(path: &str) -> anyhow::Result<(f32, Option<f32>)> {
+	let contents = fs::read_to_string(path).context(format!("Could not read path '{}'", path))?;
+	let max =
+		utils::str_to_f32(&contents).context(format!("Could not parse contents of '{}'", path))?;
+	let design = fs::read_to_string(format!("{}_design", path))
+		.ok()
+		.and_then(|x| utils::str_to_f32(&x).ok());
+	Ok((max, design))
+}
+
+/// Get the details the kernel gives about a battery in `dir`, skipping any
+/// which are missing.
+fn get_info(dir: &Path) -> Values {
+	let mut info = Values::new();
+	let read = |file| fs::read_to_string(dir.join(file)).ok();
+	if let Some(count) = read("cycle_count").and_then(|x| utils::str_to_f32(&x).ok()) {
+		info.insert("cycle_count".to_string(), count.into());
+	}
+	for key in &["technology", "model_name", "manufacturer"] {
+		if let Some(value) = read(key).filter(|x| !x.trim().is_empty()) {
+			info.insert(key.to_string(), value.trim().into());
+		}
+	}
+	info
+}
+
+/// Get the combined health of all batteries, i.e. their maximum capacity as a
+/// fraction of their design capacity.
+fn get_health(supplies: &[Supply]) -> Option<f32> {
+	let design = supplies.iter().map(|x| x.design).sum::<Option<f32>>()?;
+	let max: f32 = supplies.iter().map(|x| x.max).sum();
+	Some(max / design)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -164,10 +773,25 @@ enum Status {
 	Unknown,
 }
 
+impl Status {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Status::Charging => "Charging",
+			Status::Discharging => "Discharging",
+			Status::Full => "Full",
+			Status::NotCharging => "Not charging",
+			Status::Unknown => "Unknown",
+		}
+	}
+}
+
 #[derive(Debug, Clone, Copy)]
 enum Message {
 	Charge(f32),
+	Rate(Option<f32>),
 	Status(Status),
+	Threshold(Option<f32>),
+	Online(bool),
 }
 
 /// Convert a string to a status.
@@ -192,30 +816,117 @@ fn str_to_charge(s: &str) -> anyhow::Result<Message> {
 	Ok(Message::Charge(value))
 }
 
+/// Convert a string to a rate enum. Some drivers report a negative rate when
+/// discharging so only the magnitude is kept.
+fn str_to_rate(s: &str) -> anyhow::Result<Message> {
+	let value: f32 = s
+		.trim()
+		.parse()
+		.context(format!("Unexpected value for rate '{}'", s))?;
+	Ok(Message::Rate(Some(value.abs())))
+}
+
+/// Convert a string to a charge threshold enum.
+fn str_to_threshold(s: &str) -> anyhow::Result<Message> {
+	let value = s
+		.trim()
+		.parse()
+		.context(format!("Unexpected value for threshold '{}'", s))?;
+	Ok(Message::Threshold(Some(value)))
+}
+
+/// Convert a string to an online enum. USB supplies may report 2 when online
+/// with a programmable voltage.
+fn str_to_online(s: &str) -> anyhow::Result<Message> {
+	let value: u8 = s
+		.trim()
+		.parse()
+		.context(format!("Unexpected value for online '{}'", s))?;
+	Ok(Message::Online(value != 0))
+}
+
+/// Get the combined rate of all batteries as reported by the kernel. This is
+/// only available if every battery reports a rate and they are not all zero.
+fn kernel_rate(rates: &[Option<f32>]) -> Option<f32> {
+	let rate = rates.iter().copied().sum::<Option<f32>>()?;
+	if rate > 0.0 {
+		Some(rate)
+	} else {
+		None
+	}
+}
+
+/// Estimate the minutes remaining from the rate reported by the kernel (which is
+/// per hour), if available.
+fn kernel_estimate(status: Status, charge: f32, max: f32, rates: &[Option<f32>]) -> Option<f32> {
+	match status {
+		Status::Charging | Status::Discharging => {
+			kernel_rate(rates).map(|rate| get_gap(status, charge, max) / rate * 60.0)
+		}
+		_ => None,
+	}
+}
+
+/// Get the amount of charge left to gain or lose given the current status.
+fn get_gap(status: Status, charge: f32, max: f32) -> f32 {
+	match status {
+		Status::Charging => max - charge,
+		Status::Full => 0.0,
+		_ => charge,
+	}
+}
+
+/// Combine the statuses of multiple batteries into a single status.
+fn combine_statuses(statuses: &[Status]) -> Status {
+	if statuses.contains(&Status::Charging) {
+		Status::Charging
+	} else if statuses.contains(&Status::Discharging) {
+		Status::Discharging
+	} else if statuses.iter().all(|x| *x == Status::Full) {
+		Status::Full
+	} else if statuses.contains(&Status::NotCharging) {
+		Status::NotCharging
+	} else {
+		Status::Unknown
+	}
+}
+
 /// Continuously monitor `f` for changes, when a change occurs or more than 10
 /// checks have occurred, pipe its contents through `parse_fn` and send the
-/// results over the sender `tx`.
-fn looper<F, T>(tx: crossbeam_channel::Sender<Message>, mut f: utils::Monitor<T>, parse_fn: F)
-where
+/// results over the sender `tx` along with the index of the battery `i`.
+/// Contents which can't be parsed are sent as `fallback` if there is one.
+fn looper<F, T>(
+	tx: crossbeam_channel::Sender<(usize, Message)>,
+	i: usize,
+	mut f: utils::Monitor<T>,
+	parse_fn: F,
+	fallback: Option<Message>,
+) where
 	F: 'static + Fn(&str) -> anyhow::Result<Message> + Send,
 	T: 'static + FnMut() -> String + Send,
 {
 	thread::spawn(move || {
 		let mut prev = f.read();
-		let mut i = 0;
+		let mut i_ = 0;
 
 		for contents in f {
 			log::debug!("Contents: {}", contents);
-			if contents != prev || i > 10 {
-				let parsed = parse_fn(&contents).expect(&format!(
-					"Encountered bad value in battery file: '{}'",
-					contents
-				));
-				tx.send(parsed).unwrap();
+			if contents != prev || i_ > 10 {
+				let parsed = match (parse_fn(&contents), fallback) {
+					(Ok(parsed), _) => parsed,
+					(Err(e), Some(fallback)) => {
+						log::warn!("Ignoring bad value in battery file: {:?}", e);
+						fallback
+					}
+					(Err(_), None) => {
+						panic!("Encountered bad value in battery file: '{}'", contents)
+					}
+				};
+				tx.send((i, parsed)).unwrap();
 				prev = contents;
-				i = 0;
+				i_ = 0;
 			}
-			i += 1;
+			i_ += 1;
 		}
 	});
 }
@@ -253,6 +964,96 @@ fn get_symbol(status: Status, fraction: f32) -> String {
 	wrap_in_colour(s, fraction)
 }
 
+/// Given a status and percentage of charge, return the state of the block.
+fn get_state(status: Status, percentage: f32, warning: f32, critical: f32) -> State {
+	match status {
+		Status::Charging | Status::Full => State::Good,
+		Status::Discharging if percentage <= critical => State::Critical,
+		Status::Discharging if percentage <= warning => State::Warning,
+		_ => State::Idle,
+	}
+}
+
+/// Get the combined fraction of charge of all batteries.
+fn get_fraction(supplies: &[Supply], charges: &[f32]) -> f32 {
+	let max: f32 = supplies.iter().map(|x| x.max).sum();
+	(charges.iter().sum::<f32>() / max).min(1.0)
+}
+
+/// Collect the raw values of the batteries. Values for individual batteries are
+/// prefixed with the battery's name, e.g. `bat0_fraction`.
+fn get_values(
+	supplies: &[Supply],
+	readings: &Readings,
+	status: Status,
+	source: Option<Source>,
+	minutes: Option<f32>,
+) -> Values {
+	let mut values = Values::new();
+	let fraction = get_fraction(supplies, &readings.charges);
+	values.insert("fraction".to_string(), fraction.into());
+	values.insert("status".to_string(), status.as_str().into());
+	if let Some(minutes) = minutes {
+		values.insert("minutes_remaining".to_string(), minutes.into());
+	}
+	if let Some(source) = source {
+		values.insert("source".to_string(), source.as_str().into());
+	}
+	if let Some(threshold) = readings.thresholds.iter().flatten().next() {
+		values.insert("threshold".to_string(), (*threshold).into());
+	}
+	if let Some(health) = get_health(supplies) {
+		values.insert("health_fraction".to_string(), health.into());
+	}
+	let cycles = supplies
+		.iter()
+		.filter_map(|x| match x.info.get("cycle_count") {
+			Some(Value::Number(count)) => Some(*count),
+			_ => None,
+		})
+		.reduce(f64::max);
+	if let Some(cycles) = cycles {
+		values.insert("cycle_count".to_string(), Value::Number(cycles));
+	}
+
+	for (i, supply) in supplies.iter().enumerate() {
+		let prefix = supply.name.to_lowercase();
+		let fraction = (readings.charges[i] / supply.max).min(1.0);
+		values.insert(format!("{}_fraction", prefix), fraction.into());
+		values.insert(
+			format!("{}_status", prefix),
+			readings.statuses[i].as_str().into(),
+		);
+		if let Some(threshold) = readings.thresholds[i] {
+			values.insert(format!("{}_threshold", prefix), threshold.into());
+		}
+		if let Some(design) = supply.design {
+			let health = supply.max / design;
+			values.insert(format!("{}_health_fraction", prefix), health.into());
+		}
+		for (key, value) in supply.info.iter() {
+			values.insert(format!("{}_{}", prefix, key), value.clone());
+		}
+	}
+	values
+}
+
+/// Create a message for the block, including the raw values it was rendered
+/// from.
+fn create_message(block: &Block, state: State, fraction: f32, values: Values) -> BlockMessage {
+	let mut message = BlockMessage::new(block.clone());
+	message.state = Some(state);
+	message.percentage = Some(fraction * 100.0);
+	message.values = values;
+	message
+}
+
+/// Whether to show the detailed format after a click with `button`. Left clicks
+/// toggle it and other buttons are ignored.
+fn toggle_detailed(detailed: bool, button: u8) -> bool {
+	detailed != (button == click::LEFT)
+}
+
 /// Convert a float of minutes into a string of hours and minutes.
 fn minutes_to_string(total: f32) -> String {
 	let (mut hrs, mut mins) = (total / 60.0, total % 60.0);
@@ -265,35 +1066,124 @@ fn minutes_to_string(total: f32) -> String {
 	format!("{:.0}h{:02.0}m", hrs.floor(), mins)
 }
 
+/// The current readings of each battery and adapter.
+struct Readings {
+	charges: Vec<f32>,
+	rates: Vec<Option<f32>>,
+	statuses: Vec<Status>,
+	thresholds: Vec<Option<f32>>,
+	online: Vec<bool>,
+}
+
+/// Read the contents of `path` and start watching it for changes, which are
+/// sent over `tx` along with the index `i`. Optional files have a `fallback`
+/// which is used in place of contents which can't be parsed.
+fn watch(
+	path: &str,
+	period: f32,
+	tx: &crossbeam_channel::Sender<(usize, Message)>,
+	i: usize,
+	parse_fn: fn(&str) -> anyhow::Result<Message>,
+	fallback: Option<Message>,
+) -> anyhow::Result<Message> {
+	let mut file = utils::monitor_file(path.to_string(), period);
+	let message = match (parse_fn(&file.read()), fallback) {
+		(Ok(message), _) => message,
+		(Err(e), Some(fallback)) => {
+			log::warn!("Could not parse '{}': {:?}", path, e);
+			fallback
+		}
+		(Err(e), None) => return Err(e.context(format!("Could not parse '{}'", path))),
+	};
+	looper(tx.clone(), i, file, parse_fn, fallback);
+	Ok(message)
+}
+
 /// Start watching the appropriate files for changes and return their current
 /// contents.
 fn initialise(
-	path_to_charge_now: &str,
-	path_to_status: &str,
+	supplies: &[Supply],
+	adapters: &[Adapter],
 	period: f32,
-	tx: crossbeam_channel::Sender<Message>,
-) -> anyhow::Result<(f32, Status)> {
-	let mut charge_file = utils::monitor_file(path_to_charge_now.to_string(), period);
-	let mut status_file = utils::monitor_file(path_to_status.to_string(), period);
-
-	let current_charge = match str_to_charge(&charge_file.read())? {
-		Message::Charge(value) => value,
-		_ => unreachable!(),
+	tx: crossbeam_channel::Sender<(usize, Message)>,
+) -> anyhow::Result<Readings> {
+	let mut readings = Readings {
+		charges: Vec::new(),
+		rates: Vec::new(),
+		statuses: Vec::new(),
+		thresholds: Vec::new(),
+		online: Vec::new(),
 	};
 
-	let current_status = match str_to_status(&status_file.read())? {
-		Message::Status(value) => value,
-		_ => unreachable!(),
-	};
+	for (i, supply) in supplies.iter().enumerate() {
+		readings.charges.push(
+			match watch(&supply.path_to_now, period, &tx, i, str_to_charge, None)? {
+				Message::Charge(value) => value,
+				_ => unreachable!(),
+			},
+		);
+		readings.statuses.push(
+			match watch(&supply.path_to_status, period, &tx, i, str_to_status, None)? {
+				Message::Status(value) => value,
+				_ => unreachable!(),
+			},
+		);
+		readings.rates.push(match &supply.path_to_rate {
+			Some(path) => {
+				let fallback = Some(Message::Rate(None));
+				match watch(path, period, &tx, i, str_to_rate, fallback)? {
+					Message::Rate(value) => value,
+					_ => unreachable!(),
+				}
+			}
+			None => None,
+		});
+		readings.thresholds.push(match &supply.path_to_threshold {
+			Some(path) => {
+				let fallback = Some(Message::Threshold(None));
+				match watch(path, period, &tx, i, str_to_threshold, fallback)? {
+					Message::Threshold(value) => value,
+					_ => unreachable!(),
+				}
+			}
+			None => None,
+		});
+	}
 
-	looper(tx.clone(), charge_file, str_to_charge);
-	looper(tx.clone(), status_file, str_to_status);
+	// An adapter whose online file can't be parsed is treated as offline.
+	for (i, adapter) in adapters.iter().enumerate() {
+		let fallback = Some(Message::Online(false));
+		readings.online.push(
+			match watch(
+				&adapter.path_to_online,
+				period,
+				&tx,
+				i,
+				str_to_online,
+				fallback,
+			)? {
+				Message::Online(value) => value,
+				_ => unreachable!(),
+			},
+		);
+	}
 
-	Ok((current_charge, current_status))
+	Ok(readings)
 }
 
-fn create_full_text(symbol: String, fraction: f32, remaining: &str) -> String {
-	format!("{} {:.0}% ({})", symbol, fraction * 100.0, remaining)
+/// Render the format string. Placeholders are the block's values, with
+/// fractions also given as percentages, along with the symbol and remaining
+/// time.
+fn create_full_text(format: &str, values: &Values, symbol: String, remaining: &str) -> String {
+	let mut placeholders = values.clone();
+	for (key, value) in values.iter() {
+		if let (Some(prefix), Value::Number(fraction)) = (key.strip_suffix("fraction"), value) {
+			placeholders.insert(format!("{}percentage", prefix), (fraction * 100.0).into());
+		}
+	}
+	placeholders.insert("symbol".to_string(), symbol.as_str().into());
+	placeholders.insert("remaining".to_string(), remaining.into());
+	utils::format(format, &placeholders)
 }
 
 #[cfg(test)]
@@ -320,4 +1210,190 @@ mod test {
 		let result = wrap_in_colour("a", 0.01);
 		assert_eq!(result, "<span foreground=\'#ff0500\'>a</span>");
 	}
+
+	#[test]
+	fn test_combine_statuses() {
+		use Status::*;
+		assert_eq!(combine_statuses(&[Full, Discharging]), Discharging);
+		assert_eq!(combine_statuses(&[Charging, NotCharging]), Charging);
+		assert_eq!(combine_statuses(&[Full, Full]), Full);
+		assert_eq!(combine_statuses(&[Full, NotCharging]), NotCharging);
+	}
+
+	#[test]
+	fn test_create_full_text() {
+		let mut values = Values::new();
+		values.insert("fraction".to_string(), 0.5.into());
+		values.insert("bat1_fraction".to_string(), 0.25.into());
+		assert_eq!(
+			create_full_text(
+				"{symbol} {percentage}% {bat1_percentage}% ({remaining})",
+				&values,
+				"S".to_string(),
+				"1h00m"
+			),
+			"S 50% 25% (1h00m)"
+		);
+	}
+
+	#[test]
+	fn test_default_format_detailed() {
+		let mut values = Values::new();
+		assert_eq!(
+			default_format_detailed(&values, "{percentage}%"),
+			"{percentage}%"
+		);
+		values.insert("cycle_count".to_string(), 120.0.into());
+		assert_eq!(
+			default_format_detailed(&values, "{percentage}%"),
+			"{symbol} {cycle_count} cycles"
+		);
+		values.insert("health_fraction".to_string(), 0.8.into());
+		assert_eq!(
+			default_format_detailed(&values, "{percentage}%"),
+			"{symbol} {health_percentage}% health, {cycle_count} cycles"
+		);
+	}
+
+	#[test]
+	fn test_toggle_detailed() {
+		assert!(toggle_detailed(false, click::LEFT));
+		assert!(!toggle_detailed(true, click::LEFT));
+		assert!(toggle_detailed(true, click::RIGHT));
+		assert!(!toggle_detailed(false, click::SCROLL_UP));
+	}
+
+	#[test]
+	fn test_kernel_rate() {
+		assert_eq!(kernel_rate(&[Some(1.0), Some(2.0)]), Some(3.0));
+		assert_eq!(kernel_rate(&[Some(1.0), None]), None);
+		assert_eq!(kernel_rate(&[Some(0.0)]), None);
+		assert_eq!(
+			kernel_estimate(Status::Discharging, 20.0, 40.0, &[Some(10.0)]),
+			Some(120.0)
+		);
+		assert_eq!(
+			kernel_estimate(Status::Charging, 20.0, 40.0, &[Some(10.0)]),
+			Some(120.0)
+		);
+		assert_eq!(
+			kernel_estimate(Status::Full, 40.0, 40.0, &[Some(10.0)]),
+			None
+		);
+	}
+
+	#[test]
+	fn test_supply_new() {
+		let tmp = tempfile::tempdir().unwrap();
+		let dir = tmp.path();
+		let bat = dir.join("BAT0");
+		fs::create_dir_all(&bat).unwrap();
+		fs::write(bat.join("status"), "Discharging\n").unwrap();
+		fs::write(bat.join("capacity"), "50\n").unwrap();
+		let supply = Supply::new(&bat).unwrap();
+		assert_eq!(supply.max, 100.0);
+		assert!(supply.path_to_now.ends_with("capacity"));
+		assert_eq!(supply.path_to_rate, None);
+
+		fs::write(bat.join("energy_now"), "20000000\n").unwrap();
+		fs::write(bat.join("energy_full"), "40000000\n").unwrap();
+		fs::write(bat.join("power_now"), "10000000\n").unwrap();
+		fs::write(bat.join("energy_full_design"), "50000000\n").unwrap();
+		fs::write(bat.join("cycle_count"), "120\n").unwrap();
+		fs::write(bat.join("model_name"), "5B10W13975\n").unwrap();
+		let supply = Supply::new(&bat).unwrap();
+		assert_eq!(supply.max, 40000000.0);
+		assert_eq!(get_health(&[supply]), Some(0.8));
+		let supply = Supply::new(&bat).unwrap();
+		assert_eq!(supply.info["cycle_count"], Value::Number(120.0));
+		assert_eq!(supply.info["model_name"], "5B10W13975".into());
+		assert!(!supply.info.contains_key("manufacturer"));
+		assert!(supply.path_to_now.ends_with("energy_now"));
+		assert!(supply.path_to_rate.unwrap().ends_with("power_now"));
+
+		let bat1 = dir.join("BAT1");
+		fs::create_dir_all(&bat1).unwrap();
+		fs::write(bat1.join("status"), "Discharging\n").unwrap();
+		fs::write(bat1.join("capacity"), "50\n").unwrap();
+		let supplies = [Supply::new(&bat).unwrap(), Supply::new(&bat1).unwrap()];
+		assert!(check_units(&supplies[..1]).is_ok());
+		assert!(check_units(&supplies).is_err());
+	}
+
+	#[test]
+	fn test_not_charging_reason() {
+		assert_eq!(
+			not_charging_reason(Some(Source::Battery), &[Some(80.0)]),
+			Some("Unplugged".to_string())
+		);
+		assert_eq!(
+			not_charging_reason(Some(Source::Usb), &[None, Some(80.0)]),
+			Some("Held at 80%".to_string())
+		);
+		assert_eq!(
+			not_charging_reason(Some(Source::Mains), &[None]),
+			Some("Plugged in".to_string())
+		);
+		assert_eq!(not_charging_reason(None, &[Some(80.0)]), None);
+	}
+
+	#[test]
+	fn test_learned() {
+		let mut learned = Learned::default();
+		assert_eq!(learned.estimate(Status::Discharging, 20.0, 40.0), None);
+		learned.update(Status::Discharging, 1.0, 0.5);
+		learned.update(Status::Discharging, 2.0, 0.5);
+		learned.update(Status::Full, 4.0, 0.5);
+		assert_eq!(
+			learned.estimate(Status::Discharging, 30.0, 40.0),
+			Some(20.0)
+		);
+		assert_eq!(learned.estimate(Status::Charging, 30.0, 40.0), None);
+	}
+
+	#[test]
+	fn test_get_state() {
+		assert_eq!(get_state(Status::Charging, 5.0, 25.0, 10.0), State::Good);
+		assert_eq!(
+			get_state(Status::Discharging, 5.0, 25.0, 10.0),
+			State::Critical
+		);
+		assert_eq!(
+			get_state(Status::Discharging, 20.0, 25.0, 10.0),
+			State::Warning
+		);
+		assert_eq!(
+			get_state(Status::Discharging, 50.0, 25.0, 10.0),
+			State::Idle
+		);
+		assert_eq!(
+			get_state(Status::Discharging, 12.0, 25.0, 15.0),
+			State::Critical
+		);
+	}
+
+	#[test]
+	fn test_due_actions() {
+		let action = |percent| Action {
+			percent,
+			run: String::new(),
+		};
+		let actions = [action(15.0), action(5.0)];
+		let mut armed = [true, true];
+		assert!(due_actions(&actions, &mut armed, Status::Discharging, 20.0).is_empty());
+		assert_eq!(
+			due_actions(&actions, &mut armed, Status::Discharging, 15.0),
+			[0]
+		);
+		assert!(due_actions(&actions, &mut armed, Status::Discharging, 14.0).is_empty());
+		assert_eq!(
+			due_actions(&actions, &mut armed, Status::Discharging, 4.0),
+			[1]
+		);
+		assert!(due_actions(&actions, &mut armed, Status::Charging, 4.0).is_empty());
+		assert_eq!(
+			due_actions(&actions, &mut armed, Status::Discharging, 4.0),
+			[0, 1]
+		);
+	}
 }
@@ -7,13 +7,107 @@
 
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::fmt;
 use std::path::Path;
+use std::time::SystemTime;
 
-/// The type sent by a block to the main thread.
-pub type Message = (String, String);
+/// An update sent by a block to the main thread. Serialisation is left to the
+/// output which publishes it.
+#[derive(Debug, Clone)]
+pub struct Message {
+	pub block: Block,
+	pub state: Option<State>,
+	/// The block's main value as a percentage, if it has one.
+	pub percentage: Option<f32>,
+	/// The raw values the block was rendered from.
+	pub values: Values,
+	/// Why the block failed to update, if it did.
+	pub error: Option<String>,
+	pub timestamp: SystemTime,
+}
+
+impl Message {
+	pub fn new(block: Block) -> Message {
+		Message {
+			block,
+			state: None,
+			percentage: None,
+			values: Values::new(),
+			error: None,
+			timestamp: SystemTime::now(),
+		}
+	}
+
+	/// Create a message for a block which failed to update, keeping its last
+	/// text.
+	pub fn error(block: Block, error: String) -> Message {
+		Message {
+			state: Some(State::Error),
+			error: Some(error),
+			..Message::new(block)
+		}
+	}
+
+	pub fn name(&self) -> &str {
+		&self.block.name
+	}
+}
+
+/// Raw values computed by a block, keyed by a name which is unique within the
+/// block (e.g. `usage`).
+pub type Values = BTreeMap<String, Value>;
+
+/// A raw value computed by a block.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+	Number(f64),
+	Text(String),
+}
+
+impl From<f32> for Value {
+	fn from(value: f32) -> Self {
+		Value::Number(value as f64)
+	}
+}
 
-#[derive(Serialize)]
+impl From<f64> for Value {
+	fn from(value: f64) -> Self {
+		Value::Number(value)
+	}
+}
+
+impl From<&str> for Value {
+	fn from(value: &str) -> Self {
+		Value::Text(value.to_string())
+	}
+}
+
+impl From<String> for Value {
+	fn from(value: String) -> Self {
+		Value::Text(value)
+	}
+}
+
+/// The state of a block. This is used by outputs which style blocks by class
+/// rather than by colour (e.g. waybar). Critical blocks are marked as urgent in
+/// i3bar. Blocks monitoring a connection are disconnected when it's down, and
+/// blocks which failed to update are in error.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+	Idle,
+	Info,
+	Good,
+	Warning,
+	Critical,
+	Disconnected,
+	Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Block {
 	pub name: String,
 
@@ -29,6 +123,8 @@ pub struct Block {
 	pub separator: Option<bool>,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub separator_block_width: Option<usize>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub urgent: Option<bool>,
 }
 
 impl Block {
@@ -45,14 +141,17 @@ impl Block {
 			},
 			separator: None,
 			separator_block_width: Some(18),
+			urgent: None,
 		}
 	}
+}
 
-	pub fn to_string(&self) -> String {
+impl fmt::Display for Block {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		if let Ok(s) = serde_json::to_string(self) {
-			s
+			write!(f, "{}", s)
 		} else {
-			format!("Error in '{}'", self.name)
+			write!(f, "Error in '{}'", self.name)
 		}
 	}
 }
@@ -104,4 +203,11 @@ mod test {
 	fn create_json() {
 		Block::new("hi".to_string(), true).to_string();
 	}
+
+	#[test]
+	fn error_message() {
+		let message = Message::error(Block::new("hi".to_string(), true), "oops".to_string());
+		assert_eq!(message.state, Some(State::Error));
+		assert_eq!(message.error.as_deref(), Some("oops"));
+	}
 }
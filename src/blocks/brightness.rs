@@ -17,15 +17,15 @@
 //!
 //! - `name`: Name of the block (must be unique)
 //! - `period`: Default update period in seconds (extra updates may occur on
-//!    event changes etc)
+//!   event changes etc)
 //! - `update_signal`: Used to define what signal to listen on for immediate
-//!    retriggering of updates
+//!   retriggering of updates
 //! - `path_to_current_brightness`: Path to kernel file for current brightness
-//!    (usually something like `/sys/class/backlight/intel_backlight/brightness`
-//!    for intel based machines)
+//!   (usually something like `/sys/class/backlight/intel_backlight/brightness`
+//!   for intel based machines)
 //! - `path_to_max_brightness`: Path to kernel file for max brightness (usually
-//!    something like `/sys/class/backlight/intel_backlight/max_brightness` for
-//!    intel based machines)
+//!   something like `/sys/class/backlight/intel_backlight/max_brightness` for
+//!   intel based machines)
 
 use crate::blocks::{Block, Configure, Message, Sender, ValidatedPath};
 use crate::utils;
@@ -68,8 +68,7 @@ fn default_path_to_max_brightness() -> ValidatedPath {
 
 impl Sender for Brightness {
 	fn add_sender(&self, channel: crossbeam_channel::Sender<Message>) -> anyhow::Result<()> {
-		let name = self.get_name();
-		let mut block = Block::new(name.clone(), true);
+		let mut block = Block::new(self.get_name(), true);
 		let mut monitor =
 			utils::monitor_file(self.path_to_current_brightness.0.clone(), self.period);
 		let recv = utils::wait_for_signal(self.update_signal, self.period);
@@ -77,12 +76,15 @@ impl Sender for Brightness {
 
 		thread::spawn(move || loop {
 			let output = monitor.read();
-			block.full_text = Some(if let Ok(num) = utils::str_to_f32(&output) {
-				format!(" {:.0}%", num / max)
+			let percentage = utils::str_to_f32(&output).ok().map(|num| num / max);
+			block.full_text = Some(if let Some(perc) = percentage {
+				format!(" {:.0}%", perc)
 			} else {
 				output
 			});
-			channel.send((name.clone(), block.to_string())).unwrap();
+			let mut message = Message::new(block.clone());
+			message.percentage = percentage;
+			channel.send(message).unwrap();
 			recv.recv().unwrap();
 		});
 
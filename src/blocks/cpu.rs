@@ -19,17 +19,54 @@
 //!
 //! - `name`: Name of the block (must be unique)
 //! - `period`: Default update period in seconds (extra updates may occur on
-//!    event changes etc)
+//!   event changes etc)
 //! - `alpha`: Weight for the exponential moving average of value updates
+//! - `format`: Format of the block's text. Available placeholders are `{usage}`
+//!   (total usage as a percentage), `{user}`, `{system}`, `{iowait}` and
+//!   `{steal}` (the percentage of time spent on each), `{bars}` (the usage of
+//!   each core as a row of bars) and `{cpu0_usage}`, `{cpu1_usage}` etc for the
+//!   usage of individual cores. Where the kernel provides them (from
+//!   `/sys/devices/system/cpu/cpu*/cpufreq`), `{freq_avg}` and `{freq_max}`
+//!   give the average and highest current frequency of the cores in GHz,
+//!   `{cpu0_freq}` etc the frequency of individual cores, and `{governor}` and
+//!   `{epp}` the scaling governor and energy performance preference
+//! - `pressure`: Whether to read pressure stall information from
+//!   `/proc/pressure`, making `{cpu_some}`, `{cpu_full}`, `{memory_some}`,
+//!   `{memory_full}`, `{io_some}` and `{io_full}` available (the avg10
+//!   percentage of time some or all tasks were stalled on each resource)
+//! - `warning`: Thresholds on the block's values above which it's shown as a
+//!   warning, e.g. `{usage = 80, cpu_some = 20}`
+//! - `critical`: Thresholds on the block's values above which it's shown as
+//!   critical
+//!
+//! For example, to show the usage of each core along with time spent waiting on
+//! IO:
+//!
+//! ```toml
+//! [cpu]
+//! format = "{bars} {iowait:.1}%"
+//! ```
+//!
+//! Or to spot when the CPU is being throttled:
+//!
+//! ```toml
+//! [cpu]
+//! format = "{usage}% {freq_max:.1}GHz {governor}"
+//! ```
 
-use crate::blocks::{Block, Configure, Message, Sender};
-use crate::{ema, utils};
+use crate::blocks::{Block, Configure, Message, Sender, Values};
+use crate::{ema, pressure, utils};
 use regex::Regex;
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::thread;
 
-const PATTERN: &str = r"cpu\s+(\d+)\s+(\d+)\s+(\d+)\s+(\d+)\s+(\d+)\s+(\d+)\s+(\d+)";
+const PATTERN: &str = r"(?m)^(cpu\d*)((?:\s+\d+){4,10})\s*$";
 const PATH: &str = "/proc/stat";
+const CPUFREQ_DIR: &str = "/sys/devices/system/cpu";
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
 
 #[derive(Configure, Deserialize)]
 pub struct Cpu {
@@ -39,6 +76,14 @@ pub struct Cpu {
 	period: f32,
 	#[serde(default = "default_alpha")]
 	alpha: f32,
+	#[serde(default = "default_format")]
+	format: String,
+	#[serde(default)]
+	pressure: bool,
+	#[serde(default)]
+	warning: utils::Thresholds,
+	#[serde(default)]
+	critical: utils::Thresholds,
 }
 
 fn default_name() -> String {
@@ -53,26 +98,60 @@ fn default_alpha() -> f32 {
 	0.7
 }
 
+fn default_format() -> String {
+	"\u{f2db} {usage:.1}%".to_string()
+}
+
 impl Sender for Cpu {
 	fn add_sender(&self, channel: crossbeam_channel::Sender<Message>) -> anyhow::Result<()> {
 		let name = self.get_name();
+		let format = self.format.clone();
+		let pressure = self.pressure;
+		let (warning, critical) = (self.warning.clone(), self.critical.clone());
 		let monitor = utils::monitor_file(PATH.to_string(), self.period);
 		let mut perc = ema::Ema::new(self.alpha);
-		let mut cpu = Usage {
-			idle: 0.0,
-			total: 0.0,
-		};
+		let mut prev: HashMap<String, Times> = HashMap::new();
+		let cpufreq_dirs = cpufreq_dirs(Path::new(CPUFREQ_DIR));
 		let mut block = Block::new(name.clone(), true);
 
 		thread::spawn(move || {
 			for c in monitor {
-				let current_cpu = calc_cpu(match_proc(&c));
-				block.full_text = Some(format!(
-					" {:.1}%",
-					perc.push(calc_dcpu(&current_cpu, &cpu))
-				));
-				channel.send((name.clone(), block.to_string())).unwrap();
-				cpu = current_cpu;
+				let current = match match_proc(&c) {
+					Some(current) => current,
+					None => {
+						let error = format!("Failed to parse '{}'", PATH);
+						channel.send(Message::error(block.clone(), error)).unwrap();
+						continue;
+					}
+				};
+				let breakdowns = calc_breakdowns(&current, &prev);
+
+				let total = &breakdowns[0];
+				let usage = perc.push(total.usage);
+				let mut values = Values::new();
+				values.insert("usage".to_string(), usage.into());
+				values.insert("user".to_string(), total.user.into());
+				values.insert("system".to_string(), total.system.into());
+				values.insert("iowait".to_string(), total.iowait.into());
+				values.insert("steal".to_string(), total.steal.into());
+				for ((name, _), breakdown) in current.iter().zip(&breakdowns).skip(1) {
+					values.insert(format!("{}_usage", name), breakdown.usage.into());
+				}
+				insert_frequencies(&cpufreq_dirs, &mut values);
+				if pressure {
+					pressure::insert_values(&mut values);
+				}
+
+				let mut placeholders = values.clone();
+				let bars: String = breakdowns.iter().skip(1).map(|x| bar(x.usage)).collect();
+				placeholders.insert("bars".to_string(), bars.into());
+				block.full_text = Some(utils::format(&format, &placeholders));
+				let mut message = Message::new(block.clone());
+				message.percentage = Some(usage);
+				message.state = utils::threshold_state(&values, &warning, &critical);
+				message.values = values;
+				channel.send(message).unwrap();
+				prev = current.into_iter().collect();
 			}
 		});
 
@@ -80,44 +159,274 @@ impl Sender for Cpu {
 	}
 }
 
-struct Usage {
-	idle: f32,
-	total: f32,
+/// Time spent in each state as given by a line of `/proc/stat`, in the order
+/// of its columns. Older kernels don't have the later columns, which are then
+/// zero.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Times {
+	user: u64,
+	nice: u64,
+	system: u64,
+	idle: u64,
+	iowait: u64,
+	irq: u64,
+	softirq: u64,
+	steal: u64,
+	guest: u64,
+	guest_nice: u64,
 }
 
-fn calc_cpu(stat: regex::Captures) -> Usage {
-	// (user, nice, system, idle, iowait, irq, softirq)
-	let stats: Vec<f32> = stat
+impl Times {
+	fn from_columns(columns: &[u64]) -> Times {
+		let column = |i: usize| columns.get(i).copied().unwrap_or(0);
+		Times {
+			user: column(0),
+			nice: column(1),
+			system: column(2),
+			idle: column(3),
+			iowait: column(4),
+			irq: column(5),
+			softirq: column(6),
+			steal: column(7),
+			guest: column(8),
+			guest_nice: column(9),
+		}
+	}
+
+	/// The total time. Time spent on guests is already counted in `user` and
+	/// `nice` so isn't included again.
+	fn total(&self) -> u64 {
+		self.user
+			+ self.nice
+			+ self.system
+			+ self.idle
+			+ self.iowait
+			+ self.irq
+			+ self.softirq
+			+ self.steal
+	}
+}
+
+/// Percentages of time spent in each state between two readings.
+#[derive(Debug, PartialEq)]
+struct Breakdown {
+	usage: f32,
+	user: f32,
+	system: f32,
+	iowait: f32,
+	steal: f32,
+}
+
+/// Get the breakdown between two readings. Counters are only converted to
+/// floats once differenced, since they lose precision as they grow. Some
+/// counters (e.g. `iowait`) can go backwards, which is counted as no time. If
+/// no time has passed everything is 0%.
+fn calc_breakdown(cpu: &Times, prevcpu: &Times) -> Breakdown {
+	let delta = |now: u64, then: u64| now.saturating_sub(then) as f32;
+	let total = delta(cpu.total(), prevcpu.total());
+	let perc = |x: f32| if total > 0.0 { x / total * 100.0 } else { 0.0 };
+	let idle = delta(cpu.idle + cpu.iowait, prevcpu.idle + prevcpu.iowait);
+	Breakdown {
+		usage: if total > 0.0 { 100.0 - perc(idle) } else { 0.0 },
+		user: perc(delta(cpu.user + cpu.nice, prevcpu.user + prevcpu.nice)),
+		system: perc(delta(
+			cpu.system + cpu.irq + cpu.softirq,
+			prevcpu.system + prevcpu.irq + prevcpu.softirq,
+		)),
+		iowait: perc(delta(cpu.iowait, prevcpu.iowait)),
+		steal: perc(delta(cpu.steal, prevcpu.steal)),
+	}
+}
+
+/// Get the breakdown of each cpu since the previous reading of the cpu with the
+/// same name, since lines shift when cores go offline.
+fn calc_breakdowns(current: &[(String, Times)], prev: &HashMap<String, Times>) -> Vec<Breakdown> {
+	current
 		.iter()
-		.skip(1)
-		.map(|x| x.unwrap().as_str().parse().unwrap())
-		.collect();
+		.map(|(name, times)| {
+			let prev = prev.get(name).copied().unwrap_or_default();
+			calc_breakdown(times, &prev)
+		})
+		.collect()
+}
+
+/// Get the cpufreq directories of each core in `dir`, ordered by core.
+fn cpufreq_dirs(dir: &Path) -> Vec<(String, PathBuf)> {
+	let mut dirs: Vec<(usize, String, PathBuf)> = fs::read_dir(dir)
+		.map(|entries| {
+			entries
+				.filter_map(|x| x.ok())
+				.filter_map(|x| {
+					let name = x.file_name().to_string_lossy().to_string();
+					let index = name.strip_prefix("cpu")?.parse().ok()?;
+					let path = x.path().join("cpufreq");
+					Some((index, name, path)).filter(|x| x.2.is_dir())
+				})
+				.collect()
+		})
+		.unwrap_or_default();
+	dirs.sort();
+	dirs.into_iter()
+		.map(|(_, name, path)| (name, path))
+		.collect()
+}
+
+/// Read a cpufreq file, which may be missing depending on the driver.
+fn read_cpufreq(dir: &Path, file: &str) -> Option<String> {
+	fs::read_to_string(dir.join(file))
+		.ok()
+		.map(|x| x.trim().to_string())
+}
 
-	Usage {
-		idle: (stats[3] + stats[4]),
-		total: stats.iter().sum(),
+/// Insert the current frequency of each core (in GHz) along with their average
+/// and maximum, and the scaling governor and energy performance preference.
+fn insert_frequencies(dirs: &[(String, PathBuf)], values: &mut Values) {
+	let mut freqs = Vec::new();
+	for (name, dir) in dirs {
+		let freq = read_cpufreq(dir, "scaling_cur_freq").and_then(|x| utils::str_to_f32(&x).ok());
+		if let Some(freq) = freq {
+			let freq = freq / 1e6; // Given in kHz
+			values.insert(format!("{}_freq", name), freq.into());
+			freqs.push(freq);
+		}
+	}
+	if let Some((avg, max)) = summarise(&freqs) {
+		values.insert("freq_avg".to_string(), avg.into());
+		values.insert("freq_max".to_string(), max.into());
+	}
+	if let Some((_, dir)) = dirs.first() {
+		for (key, file) in &[
+			("governor", "scaling_governor"),
+			("epp", "energy_performance_preference"),
+		] {
+			if let Some(value) = read_cpufreq(dir, file) {
+				values.insert(key.to_string(), value.into());
+			}
+		}
 	}
 }
 
-fn calc_dcpu(cpu: &Usage, prevcpu: &Usage) -> f32 {
-	(1.0 - (cpu.idle - prevcpu.idle) / (cpu.total - prevcpu.total)) * 100.0
+/// Get the average and maximum of some values.
+fn summarise(values: &[f32]) -> Option<(f32, f32)> {
+	let max = values.iter().copied().reduce(f32::max)?;
+	Some((values.iter().sum::<f32>() / values.len() as f32, max))
 }
 
-fn match_proc(s: &str) -> regex::Captures {
+/// Get a bar whose height represents the given percentage.
+fn bar(percentage: f32) -> char {
+	let i = (percentage / 100.0 * BARS.len() as f32) as usize;
+	BARS[i.min(BARS.len() - 1)]
+}
+
+/// Get the times of the aggregate `cpu` line followed by each core's line, or
+/// `None` if the aggregate line is missing or any column can't be parsed.
+fn match_proc(s: &str) -> Option<Vec<(String, Times)>> {
 	lazy_static! {
 		static ref RE: Regex = Regex::new(PATTERN).unwrap();
 	}
-	RE.captures(s)
-		.expect(&format!("Failed to match /proc/stat contents '{}'", s))
+	let cpus = RE
+		.captures_iter(s)
+		.map(|caps| {
+			let columns = caps[2]
+				.split_whitespace()
+				.map(|x| x.parse().ok())
+				.collect::<Option<Vec<u64>>>()?;
+			Some((caps[1].to_string(), Times::from_columns(&columns)))
+		})
+		.collect::<Option<Vec<_>>>()?;
+	if cpus.first().map(|x| x.0.as_str()) != Some("cpu") {
+		return None;
+	}
+	Some(cpus)
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
-	const STATFILE: &str = "cpu  237476 0 85111 17267319 2310 34402 4846 0 0 0\n";
+	const STATFILE: &str = "cpu  237476 0 85111 17267319 2310 34402 4846 0 0 0
+cpu0 118738 0 42555 8633659 1155 17201 2423 0 0 0
+cpu1 118738 0 42556 8633660 1155 17201 2423 0 0 0
+intr 1234 0 0
+";
 
 	#[test]
 	fn regex_matches() {
-		match_proc(&STATFILE);
+		let cpus = match_proc(STATFILE).unwrap();
+		assert_eq!(
+			cpus.iter().map(|x| x.0.as_str()).collect::<Vec<_>>(),
+			["cpu", "cpu0", "cpu1"]
+		);
+		assert_eq!(cpus[0].1.system, 85111);
+		assert_eq!(cpus[0].1.guest_nice, 0);
+		assert_eq!(match_proc("cpu 99999999999999999999 0 0 0\n"), None);
+		assert_eq!(match_proc("cpu0 1 0 0 0\n"), None);
+	}
+
+	#[test]
+	fn breakdown() {
+		let prev = Times::from_columns(&[100, 0, 50, 800, 50, 0, 0, 0]);
+		let curr = Times::from_columns(&[130, 10, 60, 830, 60, 0, 0, 10]);
+		assert_eq!(
+			calc_breakdown(&curr, &prev),
+			Breakdown {
+				usage: 60.0,
+				user: 40.0,
+				system: 10.0,
+				iowait: 10.0,
+				steal: 10.0,
+			}
+		);
+		assert_eq!(calc_breakdown(&curr, &curr).usage, 0.0);
+	}
+
+	#[test]
+	fn breakdowns_by_name() {
+		let times = |idle| Times::from_columns(&[100, 0, 0, idle]);
+		let prev: HashMap<String, Times> =
+			vec![("cpu1".to_string(), times(50))].into_iter().collect();
+		let current = [
+			("cpu".to_string(), times(100)),
+			("cpu1".to_string(), times(100)),
+		];
+		let breakdowns = calc_breakdowns(&current, &prev);
+		assert_eq!(breakdowns[0].usage, 50.0);
+		assert_eq!(breakdowns[1].usage, 0.0);
+	}
+
+	#[test]
+	fn frequencies() {
+		let tmp = tempfile::tempdir().unwrap();
+		let dir = tmp.path();
+		for (name, freq) in &[("cpu10", "3000000"), ("cpu2", "1000000")] {
+			fs::create_dir_all(dir.join(name).join("cpufreq")).unwrap();
+			fs::write(dir.join(name).join("cpufreq/scaling_cur_freq"), freq).unwrap();
+		}
+		fs::write(dir.join("cpu2/cpufreq/scaling_governor"), "powersave\n").unwrap();
+		fs::create_dir_all(dir.join("cpuidle")).unwrap();
+
+		let dirs = cpufreq_dirs(dir);
+		assert_eq!(
+			dirs.iter().map(|x| x.0.as_str()).collect::<Vec<_>>(),
+			["cpu2", "cpu10"]
+		);
+		let mut values = Values::new();
+		insert_frequencies(&dirs, &mut values);
+		assert_eq!(values["freq_avg"], 2.0.into());
+		assert_eq!(values["cpu10_freq"], 3.0.into());
+		assert_eq!(values["governor"], "powersave".into());
+		assert!(!values.contains_key("epp"));
+	}
+
+	#[test]
+	fn summarise_frequencies() {
+		assert_eq!(summarise(&[1.0, 2.0, 3.0]), Some((2.0, 3.0)));
+		assert_eq!(summarise(&[]), None);
+	}
+
+	#[test]
+	fn bars() {
+		assert_eq!(bar(0.0), '▁');
+		assert_eq!(bar(50.0), '▅');
+		assert_eq!(bar(100.0), '█');
 	}
 }
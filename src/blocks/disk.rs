@@ -0,0 +1,347 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! # Disk block
+//!
+//! Use this block to get disk usage monitoring in the status bar.
+//!
+//! Usage is read with `statvfs`. When no mount points are given they're found
+//! from `/proc/self/mounts`, skipping pseudo filesystems (e.g. `proc` or
+//! `tmpfs`) and bind mounts of the same device.
+//!
+//! Typical configuration:
+//!
+//! ```toml
+//! [disk]
+//! mounts = ["/"]
+//! ```
+//!
+//! ## Configuration options
+//!
+//! - `name`: Name of the block (must be unique)
+//! - `period`: Default update period in seconds (extra updates may occur on
+//!   event changes etc)
+//! - `mounts`: Mount points to show, defaulting to all real filesystems
+//! - `labels`: Labels to show for mount points, e.g. `{"/home" = "home"}`.
+//!   Mount points without a label are labelled by their path
+//! - `inodes`: Whether to make inode usage available
+//! - `format`: Format of each mount point's text. Available placeholders are
+//!   `{label}`, `{mount}`, `{usage}` (as a percentage), `{used}`, `{free}` and
+//!   `{total}` (in human readable units, e.g. `3.2GiB`), and when `inodes` is
+//!   set `{inode_usage}` (as a percentage) and `{inodes_free}`
+//! - `separator`: Text placed between mount points
+//! - `warning`: Thresholds on the block's values above which it's shown as a
+//!   warning. Values are prefixed by their mount point, with `/` as `root` and
+//!   other characters replaced by underscores, e.g. `{root_usage = 80,
+//!   home_inode_usage = 80}`. Defaults to 85% usage of the root filesystem
+//! - `critical`: Thresholds on the block's values above which it's shown as
+//!   critical. Defaults to 95% usage of the root filesystem
+//!
+//! For example, to show the space left on the root and home partitions:
+//!
+//! ```toml
+//! [disk]
+//! mounts = ["/", "/home"]
+//! labels = {"/" = "root", "/home" = "home"}
+//! format = "{label} {free}"
+//! critical = {root_usage = 95, home_usage = 95}
+//! ```
+
+use crate::blocks::{Block, Configure, Message, Sender, Values};
+use crate::utils;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+const MOUNTS_PATH: &str = "/proc/self/mounts";
+const PSEUDO_FILESYSTEMS: [&str; 8] = [
+	"autofs",
+	"devtmpfs",
+	"overlay",
+	"ramfs",
+	"squashfs",
+	"tmpfs",
+	"tracefs",
+	"fuse.portal",
+];
+
+#[derive(Configure, Deserialize)]
+pub struct Disk {
+	#[serde(default = "default_name")]
+	name: String,
+	#[serde(default = "default_period")]
+	period: f32,
+	#[serde(default)]
+	mounts: Vec<String>,
+	#[serde(default)]
+	labels: BTreeMap<String, String>,
+	#[serde(default)]
+	inodes: bool,
+	#[serde(default = "default_format")]
+	format: String,
+	#[serde(default = "default_separator")]
+	separator: String,
+	#[serde(default = "default_warning")]
+	warning: utils::Thresholds,
+	#[serde(default = "default_critical")]
+	critical: utils::Thresholds,
+}
+
+fn default_name() -> String {
+	"disk".to_string()
+}
+
+fn default_period() -> f32 {
+	30.0
+}
+
+fn default_format() -> String {
+	"\u{f0a0} {label} {usage}%".to_string()
+}
+
+fn default_separator() -> String {
+	" ".to_string()
+}
+
+fn default_warning() -> utils::Thresholds {
+	vec![("root_usage".to_string(), 85.0)].into_iter().collect()
+}
+
+fn default_critical() -> utils::Thresholds {
+	vec![("root_usage".to_string(), 95.0)].into_iter().collect()
+}
+
+impl Disk {
+	/// Get the mount points to monitor.
+	fn mounts(&self) -> anyhow::Result<Vec<String>> {
+		if !self.mounts.is_empty() {
+			return Ok(self.mounts.clone());
+		}
+		let contents = fs::read_to_string(MOUNTS_PATH)?;
+		let mounts = parse_mounts(&contents);
+		if mounts.is_empty() {
+			anyhow::bail!("No filesystems found in '{}'", MOUNTS_PATH);
+		}
+		Ok(mounts)
+	}
+}
+
+impl Sender for Disk {
+	fn add_sender(&self, channel: crossbeam_channel::Sender<Message>) -> anyhow::Result<()> {
+		let name = self.get_name();
+		let format = self.format.clone();
+		let separator = self.separator.clone();
+		let inodes = self.inodes;
+		let (warning, critical) = (self.warning.clone(), self.critical.clone());
+		let period = Duration::from_secs_f32(self.period);
+		let mounts: Vec<(String, String)> = self
+			.mounts()?
+			.into_iter()
+			.map(|x| {
+				let label = self.labels.get(&x).cloned().unwrap_or_else(|| x.clone());
+				(x, label)
+			})
+			.collect();
+		let mut block = Block::new(name.clone(), true);
+
+		thread::spawn(move || loop {
+			let mut texts = Vec::new();
+			let mut message_values = Values::new();
+			let mut percentage = None;
+			for (mount, label) in mounts.iter() {
+				let usage = match statvfs(Path::new(mount)) {
+					Ok(usage) => usage,
+					Err(e) => {
+						log::warn!("Failed to read usage of '{}': {}", mount, e);
+						continue;
+					}
+				};
+				let values = usage.values(inodes);
+				percentage = percentage.or_else(|| Some(usage.usage() as f32));
+
+				let mut placeholders = utils::human_placeholders(&values, "_bytes");
+				placeholders.insert("mount".to_string(), mount.as_str().into());
+				placeholders.insert("label".to_string(), label.as_str().into());
+				texts.push(utils::format(&format, &placeholders));
+				for (key, value) in values {
+					message_values.insert(format!("{}_{}", value_prefix(mount), key), value);
+				}
+			}
+			if texts.is_empty() {
+				let error = "Failed to read the usage of any mounts".to_string();
+				channel.send(Message::error(block.clone(), error)).unwrap();
+				thread::sleep(period);
+				continue;
+			}
+			block.full_text = Some(texts.join(&separator));
+			let mut message = Message::new(block.clone());
+			message.percentage = percentage;
+			message.state = utils::threshold_state(&message_values, &warning, &critical);
+			message.values = message_values;
+			channel.send(message).unwrap();
+			thread::sleep(period);
+		});
+
+		Ok(())
+	}
+}
+
+/// Usage of a filesystem in bytes and inodes.
+#[derive(Debug, PartialEq)]
+struct Usage {
+	total: f64,
+	free: f64,
+	available: f64,
+	inodes: f64,
+	inodes_free: f64,
+}
+
+impl Usage {
+	/// Get the usage as a percentage of the space available to users, as `df`
+	/// reports it (space reserved for root isn't counted).
+	fn usage(&self) -> f64 {
+		let used = self.total - self.free;
+		if used + self.available > 0.0 {
+			used / (used + self.available) * 100.0
+		} else {
+			0.0
+		}
+	}
+
+	fn values(&self, inodes: bool) -> Values {
+		let mut values = Values::new();
+		values.insert("usage".to_string(), self.usage().into());
+		values.insert("used_bytes".to_string(), (self.total - self.free).into());
+		values.insert("free_bytes".to_string(), self.available.into());
+		values.insert("total_bytes".to_string(), self.total.into());
+		if inodes && self.inodes > 0.0 {
+			let inode_usage = (self.inodes - self.inodes_free) / self.inodes * 100.0;
+			values.insert("inode_usage".to_string(), inode_usage.into());
+			values.insert("inodes_free".to_string(), self.inodes_free.into());
+		}
+		values
+	}
+}
+
+/// Get the usage of the filesystem mounted at `path`.
+fn statvfs(path: &Path) -> io::Result<Usage> {
+	let path = CString::new(path.as_os_str().as_bytes())?;
+	let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+	if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+		return Err(io::Error::last_os_error());
+	}
+	let block_size = stat.f_frsize as f64;
+	Ok(Usage {
+		total: stat.f_blocks as f64 * block_size,
+		free: stat.f_bfree as f64 * block_size,
+		available: stat.f_bavail as f64 * block_size,
+		inodes: stat.f_files as f64,
+		inodes_free: stat.f_ffree as f64,
+	})
+}
+
+/// Get the mount points of real filesystems from the contents of
+/// `/proc/self/mounts`. Filesystems not backed by a device (e.g. `proc`), known
+/// pseudo filesystems and further mounts of the same device are skipped.
+fn parse_mounts(contents: &str) -> Vec<String> {
+	let mut devices = Vec::new();
+	let mut mounts = Vec::new();
+	for line in contents.lines() {
+		let fields: Vec<&str> = line.split_whitespace().collect();
+		if let [device, mount, fstype, ..] = fields[..] {
+			if !device.starts_with('/')
+				|| PSEUDO_FILESYSTEMS.contains(&fstype)
+				|| devices.contains(&device)
+			{
+				continue;
+			}
+			devices.push(device);
+			mounts.push(unescape(mount));
+		}
+	}
+	mounts
+}
+
+/// Undo the octal escaping of whitespace and backslashes in mount points, e.g.
+/// `/mnt/my\040disk`.
+fn unescape(s: &str) -> String {
+	lazy_static! {
+		static ref RE: regex::Regex = regex::Regex::new(r"\\([0-7]{3})").unwrap();
+	}
+	RE.replace_all(s, |caps: &regex::Captures| {
+		let code = u8::from_str_radix(&caps[1], 8).unwrap();
+		(code as char).to_string()
+	})
+	.to_string()
+}
+
+/// Get the prefix of a mount point's values in the block's message, e.g.
+/// `home` for `/home` or `root` for `/`.
+fn value_prefix(mount: &str) -> String {
+	let prefix: String = mount
+		.trim_matches('/')
+		.chars()
+		.map(|x| if x.is_ascii_alphanumeric() { x } else { '_' })
+		.collect();
+	if prefix.is_empty() {
+		"root".to_string()
+	} else {
+		prefix
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::blocks::Value;
+
+	#[test]
+	fn mounts() {
+		let contents = "proc /proc proc rw,relatime 0 0\n\
+			tmpfs /run tmpfs rw,nosuid,nodev 0 0\n\
+			/dev/nvme0n1p2 / ext4 rw,relatime 0 0\n\
+			/dev/nvme0n1p1 /boot vfat rw,relatime 0 0\n\
+			/dev/nvme0n1p2 /var/lib/docker ext4 rw,relatime 0 0\n\
+			/dev/loop0 /snap/core/1 squashfs ro,nodev 0 0\n\
+			/dev/sda1 /mnt/my\\040disk ext4 rw,relatime 0 0\n";
+		assert_eq!(parse_mounts(contents), ["/", "/boot", "/mnt/my disk"]);
+	}
+
+	#[test]
+	fn usage() {
+		let usage = Usage {
+			total: 100.0,
+			free: 10.0,
+			available: 5.0,
+			inodes: 50.0,
+			inodes_free: 40.0,
+		};
+		let values = usage.values(true);
+		assert_eq!(values["usage"], Value::Number(90.0 / 95.0 * 100.0));
+		assert_eq!(values["used_bytes"], Value::Number(90.0));
+		assert_eq!(values["free_bytes"], Value::Number(5.0));
+		assert_eq!(values["inode_usage"], Value::Number(20.0));
+		assert!(!usage.values(false).contains_key("inode_usage"));
+	}
+
+	#[test]
+	fn statvfs_root() {
+		let usage = statvfs(Path::new("/")).unwrap();
+		assert!(usage.total > 0.0);
+		assert!(statvfs(Path::new("/does/not/exist")).is_err());
+	}
+
+	#[test]
+	fn prefixes() {
+		assert_eq!(value_prefix("/"), "root");
+		assert_eq!(value_prefix("/mnt/my disk"), "mnt_my_disk");
+	}
+}
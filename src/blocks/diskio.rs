@@ -0,0 +1,237 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! # Disk IO block
+//!
+//! Use this block to get disk throughput monitoring in the status bar.
+//!
+//! This block reads from `/proc/diskstats` to calculate the rate at which data
+//! is read from and written to disks, and their utilisation (the share of time
+//! a disk was busy with IO). By default all physical disks are monitored, i.e.
+//! those in `/sys/block` backed by a device, so partitions and virtual devices
+//! (e.g. loop or device mapper devices) aren't counted twice.
+//!
+//! Typical configuration:
+//!
+//! ```toml
+//! [diskio]
+//! ```
+//!
+//! ## Configuration options
+//!
+//! - `name`: Name of the block (must be unique)
+//! - `period`: Default update period in seconds (extra updates may occur on
+//!   event changes etc)
+//! - `alpha`: Weight for the exponential moving average of value updates
+//! - `device`: Name of the device to monitor (e.g. `nvme0n1` or `sda1`),
+//!   defaulting to all physical disks
+//! - `format`: Format of the block's text. Available placeholders are `{read}`
+//!   and `{write}` (per second, in human readable units, e.g. `3.2MiB`) and
+//!   `{util}` (the utilisation as a percentage, the highest of any disk). The
+//!   same are available for each device prefixed by its name, e.g.
+//!   `{sda_read}` or `{sda_util}`
+//! - `warning`: Thresholds on the block's values above which it's shown as a
+//!   warning, e.g. `{util = 80}`
+//! - `critical`: Thresholds on the block's values above which it's shown as
+//!   critical
+//!
+//! For example, to monitor a single disk:
+//!
+//! ```toml
+//! [diskio]
+//! device = "nvme0n1"
+//! format = "R {read}/s W {write}/s {util:.0}%"
+//! ```
+
+use crate::blocks::{Block, Configure, Message, Sender, Values};
+use crate::rate::Speed;
+use crate::utils;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::thread;
+use std::time::Instant;
+
+const PATH: &str = "/proc/diskstats";
+const BLOCK_DIR: &str = "/sys/block";
+const SECTOR_SIZE: u64 = 512;
+
+#[derive(Configure, Deserialize)]
+pub struct Diskio {
+	#[serde(default = "default_name")]
+	name: String,
+	#[serde(default = "default_period")]
+	period: f32,
+	#[serde(default = "default_alpha")]
+	alpha: f64,
+	device: Option<String>,
+	#[serde(default = "default_format")]
+	format: String,
+	#[serde(default)]
+	warning: utils::Thresholds,
+	#[serde(default)]
+	critical: utils::Thresholds,
+}
+
+fn default_name() -> String {
+	"diskio".to_string()
+}
+
+fn default_period() -> f32 {
+	1.0
+}
+
+fn default_alpha() -> f64 {
+	0.5
+}
+
+fn default_format() -> String {
+	"\u{f1c0} {read}/s {write}/s".to_string()
+}
+
+/// The speeds of a device's counters.
+struct Speeds {
+	read: Speed,
+	write: Speed,
+	busy: Speed,
+}
+
+impl Sender for Diskio {
+	fn add_sender(&self, channel: crossbeam_channel::Sender<Message>) -> anyhow::Result<()> {
+		let name = self.get_name();
+		let format = self.format.clone();
+		let device = self.device.clone();
+		let alpha = self.alpha;
+		let (warning, critical) = (self.warning.clone(), self.critical.clone());
+		let monitor = utils::monitor_file(PATH.to_string(), self.period);
+		let mut speeds: HashMap<String, Speeds> = HashMap::new();
+		let mut block = Block::new(name.clone(), true);
+
+		thread::spawn(move || {
+			for text in monitor {
+				let now = Instant::now();
+				let mut values = Values::new();
+				let (mut read, mut write, mut util) = (None, None, None);
+				let mut present = HashSet::new();
+				for stats in parse(&text) {
+					let selected = match &device {
+						Some(device) => stats.name == *device,
+						None => Path::new(BLOCK_DIR)
+							.join(&stats.name)
+							.join("device")
+							.exists(),
+					};
+					if !selected {
+						continue;
+					}
+					present.insert(stats.name.clone());
+					let speeds = speeds.entry(stats.name.clone()).or_insert_with(|| Speeds {
+						read: Speed::new(alpha),
+						write: Speed::new(alpha),
+						busy: Speed::new(alpha),
+					});
+					let rates = (
+						speeds.read.push(stats.read_sectors * SECTOR_SIZE, now),
+						speeds.write.push(stats.write_sectors * SECTOR_SIZE, now),
+						speeds.busy.push(stats.busy_ms, now),
+					);
+					if let (Some((_, r)), Some((_, w)), Some((_, busy))) = rates {
+						// Milliseconds busy per second to a percentage.
+						let u = (busy / 10.0).min(100.0);
+						values.insert(format!("{}_read_bytes_per_sec", stats.name), r.into());
+						values.insert(format!("{}_write_bytes_per_sec", stats.name), w.into());
+						values.insert(format!("{}_util", stats.name), u.into());
+						read = Some(read.unwrap_or(0.0) + r);
+						write = Some(write.unwrap_or(0.0) + w);
+						util = Some(util.map_or(u, |x: f64| x.max(u)));
+					}
+				}
+				// Forget devices which have been removed.
+				speeds.retain(|name, _| present.contains(name));
+				if present.is_empty() {
+					let error = format!("No disks found in '{}'", PATH);
+					channel.send(Message::error(block.clone(), error)).unwrap();
+					continue;
+				}
+				let (read, write, util) = match (read, write, util) {
+					(Some(read), Some(write), Some(util)) => (read, write, util),
+					// There are no rates until the counters have been read twice.
+					_ => continue,
+				};
+				values.insert("read_bytes_per_sec".to_string(), read.into());
+				values.insert("write_bytes_per_sec".to_string(), write.into());
+				values.insert("util".to_string(), util.into());
+
+				let placeholders = utils::human_placeholders(&values, "_bytes_per_sec");
+				block.full_text = Some(utils::format(&format, &placeholders));
+				let mut message = Message::new(block.clone());
+				message.percentage = Some(util as f32);
+				message.state = utils::threshold_state(&values, &warning, &critical);
+				message.values = values;
+				channel.send(message).unwrap();
+			}
+		});
+
+		Ok(())
+	}
+}
+
+/// The counters of a device in `/proc/diskstats`.
+#[derive(Debug, PartialEq)]
+struct Stats {
+	name: String,
+	read_sectors: u64,
+	write_sectors: u64,
+	busy_ms: u64,
+}
+
+/// Parse the contents of `/proc/diskstats`. Each line gives the major and minor
+/// numbers and name of a device followed by its counters, of which the third
+/// and seventh are sectors read and written and the tenth is milliseconds spent
+/// doing IO.
+fn parse(contents: &str) -> Vec<Stats> {
+	contents
+		.lines()
+		.filter_map(|line| {
+			let fields: Vec<&str> = line.split_whitespace().collect();
+			let counter = |i: usize| fields.get(i + 2)?.parse::<u64>().ok();
+			Some(Stats {
+				name: fields.get(2)?.to_string(),
+				read_sectors: counter(3)?,
+				write_sectors: counter(7)?,
+				busy_ms: counter(10)?,
+			})
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_diskstats() {
+		let contents = "   7       0 loop0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n \
+			259       0 nvme0n1 6410 5059 2145458 9023 12274 14750 9998456 25671 0 9616 37667\n";
+		assert_eq!(
+			parse(contents),
+			[
+				Stats {
+					name: "loop0".to_string(),
+					read_sectors: 0,
+					write_sectors: 0,
+					busy_ms: 0,
+				},
+				Stats {
+					name: "nvme0n1".to_string(),
+					read_sectors: 2145458,
+					write_sectors: 9998456,
+					busy_ms: 9616,
+				}
+			]
+		);
+		assert_eq!(parse("   8       0 sda 1 2\n"), []);
+	}
+}
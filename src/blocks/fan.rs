@@ -0,0 +1,212 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! # Fan block
+//!
+//! Use this block to get fan speed monitoring in the status bar.
+//!
+//! This block reads the speed of fans in RPM from
+//! `/sys/class/hwmon/*/fan*_input`. When all fans are stopped the block is
+//! shown as idle. If any fan is stopped while the temperature (the hottest
+//! `temp*_input` sensor under `/sys/class/hwmon`) is high then it's probably
+//! stuck, and the block is shown as critical. Fans which can't be read are left
+//! out.
+//!
+//! Typical configuration:
+//!
+//! ```toml
+//! [fan]
+//! ```
+//!
+//! ## Configuration options
+//!
+//! - `name`: Name of the block (must be unique)
+//! - `period`: Default update period in seconds (extra updates may occur on
+//!   event changes etc)
+//! - `chip`: Only use fans of the chip with this name (e.g. `thinkpad`)
+//! - `label`: Only use the fan with this label (e.g. `fan1`)
+//! - `format`: Format of the block's text. Available placeholders are `{rpm}`
+//!   (the speed of the fastest fan), `{temperature}` (in °C) and the speed of
+//!   each fan named after its chip and label, e.g. `{thinkpad_fan1}`
+//! - `format_idle`: Format of the block's text when all fans are stopped, with
+//!   the same placeholders as `format`
+//! - `format_stuck`: Format of the block's text when a fan is stuck, with the
+//!   same placeholders as `format`
+//! - `stuck_temperature`: Temperature in °C at or above which stopped fans are
+//!   considered stuck
+//! - `warning`: Thresholds on the block's values above which it's shown as a
+//!   warning, e.g. `{rpm = 4000}`
+//! - `critical`: Thresholds on the block's values above which it's shown as
+//!   critical
+//!
+//! For example, to show the speed of two fans:
+//!
+//! ```toml
+//! [fan]
+//! format = "{dell_smm_fan1} {dell_smm_fan2}"
+//! ```
+
+use crate::blocks::{Block, Configure, Message, Sender, State, Values};
+use crate::hwmon;
+use crate::utils;
+use serde::Deserialize;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Configure, Deserialize)]
+pub struct Fan {
+	#[serde(default = "default_name")]
+	name: String,
+	#[serde(default = "default_period")]
+	period: f32,
+	chip: Option<String>,
+	label: Option<String>,
+	#[serde(default = "default_format")]
+	format: String,
+	#[serde(default = "default_format_idle")]
+	format_idle: String,
+	#[serde(default = "default_format_stuck")]
+	format_stuck: String,
+	#[serde(default = "default_stuck_temperature")]
+	stuck_temperature: f64,
+	#[serde(default)]
+	warning: utils::Thresholds,
+	#[serde(default)]
+	critical: utils::Thresholds,
+}
+
+fn default_name() -> String {
+	"fan".to_string()
+}
+
+fn default_period() -> f32 {
+	5.0
+}
+
+fn default_format() -> String {
+	"\u{f863} {rpm}".to_string()
+}
+
+fn default_format_idle() -> String {
+	"\u{f863} idle".to_string()
+}
+
+fn default_format_stuck() -> String {
+	"\u{f863} stuck".to_string()
+}
+
+fn default_stuck_temperature() -> f64 {
+	80.0
+}
+
+#[derive(Debug, PartialEq)]
+enum Status {
+	Spinning,
+	Idle,
+	Stuck,
+}
+
+impl Sender for Fan {
+	fn add_sender(&self, channel: crossbeam_channel::Sender<Message>) -> anyhow::Result<()> {
+		let name = self.get_name();
+		let format = self.format.clone();
+		let format_idle = self.format_idle.clone();
+		let format_stuck = self.format_stuck.clone();
+		let stuck_temperature = self.stuck_temperature;
+		let (warning, critical) = (self.warning.clone(), self.critical.clone());
+		let period = Duration::from_secs_f32(self.period);
+		let mut fans = hwmon::sensors("fan");
+		fans.retain(|x| x.matches(self.chip.as_deref(), self.label.as_deref()));
+		if fans.is_empty() {
+			anyhow::bail!("No fans found");
+		}
+		let temperatures = hwmon::sensors("temp");
+		let mut block = Block::new(name.clone(), true);
+
+		thread::spawn(move || loop {
+			let mut values = Values::new();
+			// Fans which fail to read are left out rather than counted as stopped.
+			let rpms: Vec<f64> = fans
+				.iter()
+				.filter_map(|fan| {
+					let rpm = fan.read()?;
+					values.insert(fan.key(), rpm.into());
+					Some(rpm)
+				})
+				.collect();
+			let rpm = match rpms.iter().cloned().reduce(f64::max) {
+				Some(rpm) => rpm,
+				None => {
+					let error = "Failed to read any fans".to_string();
+					channel.send(Message::error(block.clone(), error)).unwrap();
+					thread::sleep(period);
+					continue;
+				}
+			};
+			values.insert("rpm".to_string(), rpm.into());
+			let temperature = temperatures
+				.iter()
+				.filter_map(|x| x.read())
+				.reduce(f64::max)
+				.map(|x| x / 1000.0);
+			if let Some(temperature) = temperature {
+				values.insert("temperature".to_string(), temperature.into());
+			}
+
+			let status = get_status(&rpms, temperature, stuck_temperature);
+			let format = match status {
+				Status::Spinning => &format,
+				Status::Idle => &format_idle,
+				Status::Stuck => &format_stuck,
+			};
+			block.full_text = Some(utils::format(format, &values));
+			let mut message = Message::new(block.clone());
+			message.state = match status {
+				Status::Stuck => Some(State::Critical),
+				Status::Idle => {
+					utils::threshold_state(&values, &warning, &critical).or(Some(State::Idle))
+				}
+				Status::Spinning => utils::threshold_state(&values, &warning, &critical),
+			};
+			message.values = values;
+			channel.send(message).unwrap();
+			thread::sleep(period);
+		});
+
+		Ok(())
+	}
+}
+
+/// Get the status of the fans from the speeds of those which could be read and
+/// the temperature (in °C). A fan is considered stuck if it's stopped while
+/// it's hot.
+fn get_status(rpms: &[f64], temperature: Option<f64>, stuck_temperature: f64) -> Status {
+	let hot = temperature.is_some_and(|x| x >= stuck_temperature);
+	if hot && rpms.iter().any(|x| *x <= 0.0) {
+		Status::Stuck
+	} else if rpms.iter().any(|x| *x > 0.0) {
+		Status::Spinning
+	} else {
+		Status::Idle
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn status() {
+		assert_eq!(get_status(&[0.0, 2000.0], Some(90.0), 80.0), Status::Stuck);
+		assert_eq!(
+			get_status(&[0.0, 2000.0], Some(45.0), 80.0),
+			Status::Spinning
+		);
+		assert_eq!(get_status(&[2000.0], Some(90.0), 80.0), Status::Spinning);
+		assert_eq!(get_status(&[0.0], Some(45.0), 80.0), Status::Idle);
+		assert_eq!(get_status(&[0.0], None, 80.0), Status::Idle);
+		assert_eq!(get_status(&[0.0, 0.0], Some(80.0), 80.0), Status::Stuck);
+	}
+}
@@ -0,0 +1,205 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! # Load block
+//!
+//! Use this block to get the system load average in the status bar.
+//!
+//! This block reads from `/proc/loadavg`. A load average equal to the number of
+//! cores means that on average every core was busy (or had tasks waiting on
+//! IO).
+//!
+//! Typical configuration:
+//!
+//! ```toml
+//! [load]
+//! ```
+//!
+//! ## Configuration options
+//!
+//! - `name`: Name of the block (must be unique)
+//! - `period`: Default update period in seconds (extra updates may occur on
+//!   event changes etc)
+//! - `format`: Format of the block's text. Available placeholders are `{load1}`,
+//!   `{load5}` and `{load15}` (the 1, 5 and 15 minute load averages),
+//!   `{running}` and `{tasks}` (the number of runnable and total tasks) and
+//!   `{cores}` (the number of cores)
+//! - `normalise`: Whether to divide the load averages by the number of cores,
+//!   so that `1.0` means fully loaded whatever the machine
+//! - `warning`: Thresholds on the block's values above which it's shown as a
+//!   warning. Thresholds on the load averages are per core (whether or not
+//!   they're normalised), e.g. `{load5 = 1.0}` is reached once the 5 minute
+//!   load average reaches the number of cores
+//! - `critical`: Thresholds on the block's values above which it's shown as
+//!   critical
+//!
+//! For example, to show normalised load averages and warn when the machine is
+//! overloaded:
+//!
+//! ```toml
+//! [load]
+//! format = "{load1:.2} {load5:.2} {load15:.2} ({running}/{tasks})"
+//! normalise = true
+//! warning = {load5 = 1.0}
+//! critical = {load5 = 2.0}
+//! ```
+
+use crate::blocks::{Block, Configure, Message, Sender, Values};
+use crate::utils;
+use serde::Deserialize;
+use std::thread;
+
+const PATH: &str = "/proc/loadavg";
+const LOADS: [&str; 3] = ["load1", "load5", "load15"];
+
+#[derive(Configure, Deserialize)]
+pub struct Load {
+	#[serde(default = "default_name")]
+	name: String,
+	#[serde(default = "default_period")]
+	period: f32,
+	#[serde(default = "default_format")]
+	format: String,
+	#[serde(default)]
+	normalise: bool,
+	#[serde(default)]
+	warning: utils::Thresholds,
+	#[serde(default)]
+	critical: utils::Thresholds,
+}
+
+fn default_name() -> String {
+	"load".to_string()
+}
+
+fn default_period() -> f32 {
+	5.0
+}
+
+fn default_format() -> String {
+	"\u{f0ae} {load1:.2} {load5:.2} {load15:.2}".to_string()
+}
+
+impl Sender for Load {
+	fn add_sender(&self, channel: crossbeam_channel::Sender<Message>) -> anyhow::Result<()> {
+		let name = self.get_name();
+		let format = self.format.clone();
+		let normalise = self.normalise;
+		let (warning, critical) = (self.warning.clone(), self.critical.clone());
+		let monitor = utils::monitor_file(PATH.to_string(), self.period);
+		let cores = online_cores();
+		let mut block = Block::new(name.clone(), true);
+
+		thread::spawn(move || {
+			for text in monitor {
+				let load = match parse(&text) {
+					Some(load) => load,
+					None => {
+						let error = format!("Failed to parse '{}'", PATH);
+						log::warn!("{}", error);
+						channel.send(Message::error(block.clone(), error)).unwrap();
+						continue;
+					}
+				};
+				let per_core = load.values(cores, true);
+				let values = if normalise {
+					per_core.clone()
+				} else {
+					load.values(cores, false)
+				};
+				block.full_text = Some(utils::format(&format, &values));
+				let mut message = Message::new(block.clone());
+				message.percentage = Some((load.load1 / cores * 100.0) as f32);
+				message.state = utils::threshold_state(&per_core, &warning, &critical);
+				message.values = values;
+				channel.send(message).unwrap();
+			}
+		});
+
+		Ok(())
+	}
+}
+
+/// The number of online cores. The load average counts tasks on every core, so
+/// unlike `thread::available_parallelism` this isn't limited by the affinity or
+/// CPU quota of rs-blocks itself.
+fn online_cores() -> f64 {
+	match unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) } {
+		x if x > 0 => x as f64,
+		_ => 1.0,
+	}
+}
+
+#[derive(Debug, PartialEq)]
+struct LoadAvg {
+	load1: f64,
+	load5: f64,
+	load15: f64,
+	running: u32,
+	tasks: u32,
+}
+
+impl LoadAvg {
+	/// Get the values of the block, optionally with the load averages divided by
+	/// the number of cores.
+	fn values(&self, cores: f64, per_core: bool) -> Values {
+		let scale = if per_core { cores } else { 1.0 };
+		let mut values = Values::new();
+		for (key, load) in LOADS.iter().zip(&[self.load1, self.load5, self.load15]) {
+			values.insert(key.to_string(), (load / scale).into());
+		}
+		values.insert("running".to_string(), f64::from(self.running).into());
+		values.insert("tasks".to_string(), f64::from(self.tasks).into());
+		values.insert("cores".to_string(), cores.into());
+		values
+	}
+}
+
+/// Parse the contents of `/proc/loadavg`, e.g. `0.52 0.58 0.59 2/1187 4242`.
+fn parse(s: &str) -> Option<LoadAvg> {
+	let mut fields = s.split_whitespace();
+	let mut load = || fields.next()?.parse().ok();
+	let (load1, load5, load15) = (load()?, load()?, load()?);
+	let (running, tasks) = fields.next()?.split_once('/')?;
+	Some(LoadAvg {
+		load1,
+		load5,
+		load15,
+		running: running.parse().ok()?,
+		tasks: tasks.parse().ok()?,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::blocks::Value;
+
+	#[test]
+	fn parse_loadavg() {
+		let load = parse("0.52 0.58 0.59 2/1187 4242\n").unwrap();
+		assert_eq!(
+			load,
+			LoadAvg {
+				load1: 0.52,
+				load5: 0.58,
+				load15: 0.59,
+				running: 2,
+				tasks: 1187,
+			}
+		);
+		assert_eq!(parse("0.52 0.58"), None);
+	}
+
+	#[test]
+	fn normalised_values() {
+		let load = parse("2.0 4.0 8.0 1/100 1").unwrap();
+		let values = load.values(4.0, true);
+		assert_eq!(values["load1"], Value::Number(0.5));
+		assert_eq!(values["load15"], Value::Number(2.0));
+		assert_eq!(values["tasks"], Value::Number(100.0));
+		assert_eq!(load.values(4.0, false)["load5"], Value::Number(4.0));
+	}
+}
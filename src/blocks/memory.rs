@@ -6,7 +6,9 @@
 //! # Memory block
 //!
 //! Use this block to get memory monitoring in the status bar. Reads from
-//! `/proc/meminfo` to calculate memory usage.
+//! `/proc/meminfo` to calculate memory usage. Memory which is available to be
+//! reclaimed (e.g. the page cache) isn't counted as used, matching the
+//! "available" column of `free`.
 //!
 //! Typical configuration:
 //!
@@ -18,17 +20,38 @@
 //!
 //! - `name`: Name of the block (must be unique)
 //! - `period`: Default update period in seconds (extra updates may occur on
-//!    event changes etc)
+//!   event changes etc)
 //! - `alpha`: Weight for the exponential moving average of value updates
+//! - `format`: Format of the block's text. Available placeholders are `{usage}`
+//!   and `{swap_usage}` (as percentages), and `{used}`, `{total}`,
+//!   `{available}`, `{buffers}`, `{cached}`, `{shmem}`, `{swap_used}` and
+//!   `{swap_total}` (in human readable units, e.g. `3.2GiB`)
+//! - `pressure`: Whether to read pressure stall information from
+//!   `/proc/pressure`, making `{cpu_some}`, `{cpu_full}`, `{memory_some}`,
+//!   `{memory_full}`, `{io_some}` and `{io_full}` available (the avg10
+//!   percentage of time some or all tasks were stalled on each resource)
+//! - `warning`: Thresholds on the block's values above which it's shown as a
+//!   warning, e.g. `{usage = 80, memory_some = 10}`
+//! - `critical`: Thresholds on the block's values above which it's shown as
+//!   critical
+//!
+//! For example, to show the memory and swap used:
+//!
+//! ```toml
+//! [memory]
+//! format = "{used}/{total} swap {swap_used}"
+//! ```
 
-use crate::blocks::{Block, Configure, Message, Sender};
-use crate::{ema, utils};
+use crate::blocks::{Block, Configure, Message, Sender, Values};
+use crate::{ema, pressure, utils};
 use regex::Regex;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::thread;
 
 const MEMPATH: &str = "/proc/meminfo";
-const PATTERN: &str = r"(?s)MemTotal:\s+(\d+).+MemFree:\s+(\d+)";
+const PATTERN: &str = r"(?m)^(\S+):\s+(\d+)";
+const KIB: f64 = 1024.0;
 
 #[derive(Configure, Deserialize)]
 pub struct Memory {
@@ -38,6 +61,14 @@ pub struct Memory {
 	period: f32,
 	#[serde(default = "default_alpha")]
 	alpha: f32,
+	#[serde(default = "default_format")]
+	format: String,
+	#[serde(default)]
+	pressure: bool,
+	#[serde(default)]
+	warning: utils::Thresholds,
+	#[serde(default)]
+	critical: utils::Thresholds,
 }
 
 fn default_name() -> String {
@@ -52,18 +83,36 @@ fn default_alpha() -> f32 {
 	0.5
 }
 
+fn default_format() -> String {
+	"\u{f538} {usage:.1}%".to_string()
+}
+
 impl Sender for Memory {
 	fn add_sender(&self, channel: crossbeam_channel::Sender<Message>) -> anyhow::Result<()> {
 		let name = self.get_name();
+		let format = self.format.clone();
+		let pressure = self.pressure;
+		let (warning, critical) = (self.warning.clone(), self.critical.clone());
 		let monitor = utils::monitor_file(MEMPATH.to_string(), self.period);
 		let mut mem = ema::Ema::new(self.alpha);
 		let mut block = Block::new(name.clone(), true);
 
 		thread::spawn(move || {
 			for text in monitor {
-				let perc = get_mem_percentage(match_mem_stats(&text));
-				block.full_text = Some(format!(" {:.1}%", mem.push(perc) * 100.0));
-				channel.send((name.clone(), block.to_string())).unwrap();
+				let stats = match_mem_stats(&text);
+				let usage = mem.push(get_mem_percentage(&stats)) * 100.0;
+				let mut values = get_values(&stats);
+				values.insert("usage".to_string(), usage.into());
+				if pressure {
+					pressure::insert_values(&mut values);
+				}
+				let placeholders = utils::human_placeholders(&values, "_bytes");
+				block.full_text = Some(utils::format(&format, &placeholders));
+				let mut message = Message::new(block.clone());
+				message.percentage = Some(usage);
+				message.state = utils::threshold_state(&values, &warning, &critical);
+				message.values = values;
+				channel.send(message).unwrap();
 			}
 		});
 
@@ -71,41 +120,105 @@ impl Sender for Memory {
 	}
 }
 
+/// Memory statistics in bytes.
 #[derive(Debug, PartialEq)]
 struct MemStats {
 	total: f64,
-	free: f64,
+	available: f64,
+	buffers: f64,
+	cached: f64,
+	shmem: f64,
+	swap_total: f64,
+	swap_free: f64,
 }
 
 fn match_mem_stats(s: &str) -> MemStats {
 	lazy_static! {
 		static ref RE: Regex = Regex::new(PATTERN).unwrap();
 	}
-	let caps = RE.captures(s).unwrap();
+	let fields: HashMap<&str, f64> = RE
+		.captures_iter(s)
+		.map(|caps| {
+			let (key, value) = (caps.get(1).unwrap(), caps.get(2).unwrap());
+			(key.as_str(), value.as_str().parse::<f64>().unwrap() * KIB)
+		})
+		.collect();
+	let field = |key| fields.get(key).copied().unwrap_or(0.0);
+	let (buffers, cached) = (field("Buffers"), field("Cached"));
 	MemStats {
-		total: caps.get(1).unwrap().as_str().parse().unwrap(),
-		free: caps.get(2).unwrap().as_str().parse().unwrap(),
+		total: field("MemTotal"),
+		// Kernels before 3.14 don't estimate the available memory.
+		available: fields
+			.get("MemAvailable")
+			.copied()
+			.unwrap_or_else(|| field("MemFree") + buffers + cached),
+		buffers,
+		cached,
+		shmem: field("Shmem"),
+		swap_total: field("SwapTotal"),
+		swap_free: field("SwapFree"),
 	}
 }
 
-fn get_mem_percentage(mem: MemStats) -> f32 {
-	1.0 - mem.free as f32 / mem.total as f32
+fn get_mem_percentage(mem: &MemStats) -> f32 {
+	1.0 - (mem.available / mem.total) as f32
+}
+
+/// Get the raw values of the memory statistics.
+fn get_values(mem: &MemStats) -> Values {
+	let mut values = Values::new();
+	let swap_used = mem.swap_total - mem.swap_free;
+	for (key, bytes) in &[
+		("used", mem.total - mem.available),
+		("total", mem.total),
+		("available", mem.available),
+		("buffers", mem.buffers),
+		("cached", mem.cached),
+		("shmem", mem.shmem),
+		("swap_used", swap_used),
+		("swap_total", mem.swap_total),
+	] {
+		values.insert(format!("{}_bytes", key), (*bytes).into());
+	}
+	if mem.swap_total > 0.0 {
+		values.insert(
+			"swap_usage".to_string(),
+			(swap_used / mem.swap_total * 100.0).into(),
+		);
+	}
+	values
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
-	const MEMFILE: &str = "MemTotal:  16134372 kB\nMemFree:  2757408 kB\n";
+	use crate::blocks::Value;
+	const MEMFILE: &str = "MemTotal:  16134372 kB\nMemFree:  2757408 kB\nMemAvailable:  8067186 kB\nBuffers:  1024 kB\nCached:  4096 kB\nSwapCached:  0 kB\nActive(anon):  12 kB\nShmem:  512 kB\nSwapTotal:  2048 kB\nSwapFree:  1536 kB\n";
 
 	#[test]
 	fn regex_matches() {
-		let mem = match_mem_stats(&MEMFILE);
+		let mem = match_mem_stats(MEMFILE);
 		assert_eq!(
 			mem,
 			MemStats {
-				total: 16134372.0,
-				free: 2757408.0
+				total: 16134372.0 * KIB,
+				available: 8067186.0 * KIB,
+				buffers: 1024.0 * KIB,
+				cached: 4096.0 * KIB,
+				shmem: 512.0 * KIB,
+				swap_total: 2048.0 * KIB,
+				swap_free: 1536.0 * KIB,
 			}
 		);
+		assert_eq!(get_mem_percentage(&mem), 0.5);
+		assert_eq!(get_values(&mem)["swap_usage"], Value::Number(25.0));
+	}
+
+	#[test]
+	fn without_mem_available() {
+		let mem = match_mem_stats(
+			"MemTotal:  4096 kB\nMemFree:  1024 kB\nBuffers:  512 kB\nCached:  512 kB\n",
+		);
+		assert_eq!(mem.available, 2048.0 * KIB);
 	}
 }
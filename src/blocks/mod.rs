@@ -13,9 +13,15 @@ pub mod battery;
 pub mod block;
 pub mod brightness;
 pub mod cpu;
+pub mod disk;
+pub mod diskio;
+pub mod fan;
+pub mod load;
 pub mod memory;
 pub mod network;
+pub mod temperature;
 pub mod time;
+pub mod uptime;
 pub mod volume;
 
-pub use block::{Block, Configure, Message, Sender, ValidatedPath};
+pub use block::{Block, Configure, Message, Sender, State, ValidatedPath, Value, Values};
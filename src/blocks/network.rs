@@ -7,31 +7,68 @@
 //!
 //! Use this block to get network monitoring in the status bar.
 //!
+//! By default the interface of the default route (from `/proc/net/route`) is
+//! monitored, following it as it changes (e.g. when switching from wifi to
+//! ethernet). Addresses are read with `ip` and SSIDs with `iw` (by default),
+//! these are refreshed every 10 seconds. Today's traffic totals are saved under
+//! `$XDG_STATE_HOME/rs-blocks/` so they survive restarts.
+//!
 //! Typical configuration:
 //!
 //! ```toml
 //! [network]
-//! path_to_rx = "/sys/class/net/wlan0/statistics/rx_bytes"
-//! path_to_tx = "/sys/class/net/wlan0/statistics/tx_bytes"
 //! ```
 //!
 //! ## Configuration options
 //!
 //! - `name`: Name of the block (must be unique)
 //! - `period`: Default update period in seconds (extra updates may occur on
-//!    event changes etc)
+//!   event changes etc)
 //! - `alpha`: Weight for the exponential moving average of value updates
+//! - `interface`: Interface to monitor, either `"auto"` for the interface of the
+//!   default route, the name of an interface (e.g. `"wlan0"`) or a list of
+//!   interfaces whose traffic is summed (e.g. `["eth0", "wlan0"]`)
+//! - `format`: Format of the block's text. Available placeholders are
+//!   `{interface}`, `{rx}` and `{tx}` (in kB/s), `{link}` (`up` or `down`),
+//!   `{ipv4}` and `{ipv6}`, and for wireless interfaces `{quality}` (as a
+//!   percentage), `{signal}` (in dBm) and `{ssid}`. Traffic today (since local
+//!   midnight) is given by `{rx_total}` and `{tx_total}` and since the block
+//!   started by `{rx_session}` and `{tx_session}`
+//! - `format_disconnected`: Format of the block's text when the link is down or
+//!   there's no default route, with the same placeholders as `format`. The block
+//!   is then in the `disconnected` state (which e.g. waybar gives as its class)
+//! - `ssid_command`: Command to get the SSID of a wireless interface, whose
+//!   output should contain a line like `SSID: <name>` (`{interface}` is
+//!   replaced with the interface's name)
+//! - `quota`: Amount of traffic (received and transmitted) in MiB allowed per
+//!   day, above which the block is shown as a warning
 //! - `path_to_rx`: Path to the file to monitor for network receiving traffic
-//!    (usually something like `/sys/class/net/<DEVICE>/statistics/rx_bytes`
-//!    where `<DEVICE>` is the network device to monitor)
+//!   (usually something like `/sys/class/net/<DEVICE>/statistics/rx_bytes`
+//!   where `<DEVICE>` is the network device to monitor). If given then
+//!   `path_to_tx` must also be given and `interface` is ignored
 //! - `path_to_tx`: Path to the file to monitor for network transmission traffic
-//!    (usually something like `/sys/class/net/<DEVICE>/statistics/tx_bytes`
-//!    where `<DEVICE>` is the network device to monitor)
+//!   (usually something like `/sys/class/net/<DEVICE>/statistics/tx_bytes`
+//!   where `<DEVICE>` is the network device to monitor)
 
-use crate::blocks::{Block, Configure, Message, Sender, ValidatedPath};
-use crate::utils;
-use serde::Deserialize;
+use crate::blocks::{Block, Configure, Message, Sender, State, ValidatedPath, Values};
+use crate::rate::Speed;
+use crate::{shutdown, state, utils};
+use chrono::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+
+const NET_DIR: &str = "/sys/class/net";
+const ROUTE_PATH: &str = "/proc/net/route";
+const WIRELESS_PATH: &str = "/proc/net/wireless";
+const DETAILS_PERIOD: Duration = Duration::from_secs(10);
+const SAVE_PERIOD: Duration = Duration::from_secs(60);
+const MIB: f64 = 1024.0 * 1024.0;
 
 #[derive(Configure, Deserialize)]
 pub struct Network {
@@ -39,8 +76,19 @@ pub struct Network {
 	name: String,
 	#[serde(default = "default_period")]
 	period: f32,
-	path_to_rx: ValidatedPath,
-	path_to_tx: ValidatedPath,
+	#[serde(default = "default_alpha")]
+	alpha: f64,
+	#[serde(default = "default_interface")]
+	interface: Interface,
+	#[serde(default = "default_format")]
+	format: String,
+	#[serde(default = "default_format_disconnected")]
+	format_disconnected: String,
+	#[serde(default = "default_ssid_command")]
+	ssid_command: String,
+	quota: Option<f64>,
+	path_to_rx: Option<ValidatedPath>,
+	path_to_tx: Option<ValidatedPath>,
 }
 
 fn default_name() -> String {
@@ -51,60 +99,476 @@ fn default_period() -> f32 {
 	1.0
 }
 
+fn default_alpha() -> f64 {
+	0.5
+}
+
+fn default_interface() -> Interface {
+	Interface::One("auto".to_string())
+}
+
+fn default_format() -> String {
+	"{interface} <span foreground='#ccffcc'>\u{f0ab} {rx:.1}</span> <span foreground='#ffcccc'>\u{f0aa} {tx:.1}</span>".to_string()
+}
+
+fn default_format_disconnected() -> String {
+	"<span foreground='#888888'>disconnected</span>".to_string()
+}
+
+fn default_ssid_command() -> String {
+	"iw dev {interface} link".to_string()
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+enum Interface {
+	One(String),
+	Many(Vec<String>),
+}
+
+impl Network {
+	/// Get where to read the traffic counters from.
+	fn counters(&self) -> anyhow::Result<Counters> {
+		match (&self.path_to_rx, &self.path_to_tx) {
+			(Some(rx), Some(tx)) => Ok(Counters::Paths {
+				interface: interface_of(&rx.0),
+				rx: rx.0.clone(),
+				tx: tx.0.clone(),
+			}),
+			(None, None) => Ok(match &self.interface {
+				Interface::One(x) if x == "auto" => Counters::Auto,
+				Interface::One(x) => Counters::Interfaces(vec![x.clone()]),
+				Interface::Many(x) => Counters::Interfaces(x.clone()),
+			}),
+			_ => anyhow::bail!("Both or neither of path_to_rx and path_to_tx must be given"),
+		}
+	}
+}
+
 impl Sender for Network {
 	fn add_sender(&self, channel: crossbeam_channel::Sender<Message>) -> anyhow::Result<()> {
 		let name = self.get_name();
-		let rx_file = utils::monitor_file(self.path_to_rx.0.clone(), self.period);
-		let mut tx_file = utils::monitor_file(self.path_to_tx.0.clone(), self.period);
-		let coef = 1.0 / (self.period * 1024.0); // Report in kB
-		let mut rx = Speed::new(coef);
-		let mut tx = Speed::new(coef);
-		let mut first = true;
-		let mut block = Block::new(name.clone(), true);
-
-		thread::spawn(move || {
-			for rx_ in rx_file {
-				rx.push(utils::str_to_f32(&rx_).unwrap());
-				tx.push(utils::str_to_f32(&tx_file.read()).unwrap());
-
-				if first {
-					first = false;
-				} else {
-					block.full_text = Some(format!(
-						"<span foreground='#ccffcc'>\u{f0ab} {:.1}</span> <span foreground='#ffcccc'>\u{f0aa} {:.1}</span>",
-						rx.calc_speed(),
-						tx.calc_speed()
-					));
-					channel.send((name.clone(), block.to_string())).unwrap();
+		let format = self.format.clone();
+		let format_disconnected = self.format_disconnected.clone();
+		let ssid_command = self.ssid_command.clone();
+		let counters = self.counters()?;
+		let period = Duration::from_secs_f32(self.period);
+		let mut rx = Speed::new(self.alpha);
+		let mut tx = Speed::new(self.alpha);
+		let mut current = None;
+		let mut was_up = false;
+		let mut details = Details::default();
+		let mut refreshed = Instant::now();
+		let quota = self.quota;
+		let mut session = (0, 0);
+		let daily: Arc<Mutex<Daily>> = Arc::new(Mutex::new(state::load(&name).unwrap_or_default()));
+		{
+			let (name, daily) = (name.clone(), daily.clone());
+			shutdown::register(move || save_daily(&name, &daily.lock().unwrap()));
+		}
+		let mut saved = Instant::now();
+		let mut unsaved = false;
+		let mut block = Block::new(name, true);
+
+		thread::spawn(move || loop {
+			let (interfaces, rx_, tx_) = counters.read().unwrap_or_default();
+			let now = Instant::now();
+			let interface = interfaces.join("+");
+			let links: Vec<Link> = interfaces.iter().map(|x| read_link(x)).collect();
+			let up = links.iter().any(|x| x.up);
+
+			// Counters of different interfaces aren't comparable so start afresh
+			// when the interface changes.
+			let changed = current.as_ref() != Some(&interface) || up != was_up;
+			if changed {
+				current = Some(interface.clone());
+				was_up = up;
+				details = Details::default();
+				rx.reset();
+				tx.reset();
+			}
+			let speeds = (rx.push(rx_, now), tx.push(tx_, now));
+
+			let (rx_delta, tx_delta) = (speeds.0.map_or(0, |x| x.0), speeds.1.map_or(0, |x| x.0));
+			session = (session.0 + rx_delta, session.1 + tx_delta);
+			let today = Local::now().format("%Y-%m-%d").to_string();
+			let (daily_rx, daily_tx) = {
+				let mut daily = daily.lock().unwrap();
+				if daily.date != today || rx_delta + tx_delta > 0 {
+					daily.add(&today, rx_delta, tx_delta);
+					unsaved = true;
+				}
+				if unsaved && saved.elapsed() >= SAVE_PERIOD {
+					save_daily(&block.name, &daily);
+					saved = Instant::now();
+					unsaved = false;
 				}
+				(daily.rx, daily.tx)
+			};
+
+			// Details of the first interface which is up.
+			let primary = interfaces.iter().zip(&links).find(|(_, link)| link.up);
+			if let Some((name, link)) = primary {
+				if changed || refreshed.elapsed() >= DETAILS_PERIOD {
+					details = read_details(name, link.is_wireless(), &ssid_command);
+					refreshed = Instant::now();
+				}
+			}
+
+			let mut values = Values::new();
+			values.insert("interface".to_string(), interface.as_str().into());
+			values.insert("link".to_string(), if up { "up" } else { "down" }.into());
+			if let Some((_, link)) = primary {
+				link.insert_values(&mut values);
+			}
+			details.insert_values(&mut values);
+			for (key, bytes) in &[
+				("rx_total", daily_rx),
+				("tx_total", daily_tx),
+				("rx_session", session.0),
+				("tx_session", session.1),
+			] {
+				values.insert(format!("{}_bytes", key), (*bytes as f64).into());
+			}
+			let over_quota = quota.is_some_and(|x| (daily_rx + daily_tx) as f64 >= x * MIB);
+
+			let mut placeholders = utils::human_placeholders(&values, "_bytes");
+
+			if !up {
+				block.full_text = Some(utils::format(&format_disconnected, &placeholders));
+				let mut message = Message::new(block.clone());
+				message.state = Some(State::Disconnected);
+				message.values = values;
+				channel.send(message).unwrap();
+			} else if let (Some((_, rx_speed)), Some((_, tx_speed))) = speeds {
+				values.insert("rx_bytes_per_sec".to_string(), rx_speed.into());
+				values.insert("tx_bytes_per_sec".to_string(), tx_speed.into());
+				placeholders.insert("rx".to_string(), (rx_speed / 1024.0).into()); // Report in kB
+				placeholders.insert("tx".to_string(), (tx_speed / 1024.0).into());
+				block.full_text = Some(utils::format(&format, &placeholders));
+				let mut message = Message::new(block.clone());
+				if over_quota {
+					message.state = Some(State::Warning);
+				}
+				message.values = values;
+				channel.send(message).unwrap();
 			}
+			thread::sleep(period);
 		});
 
 		Ok(())
 	}
 }
 
-struct Speed {
-	curr: f32,
-	prev: f32,
-	coef: f32,
+/// Where traffic counters are read from.
+enum Counters {
+	Paths {
+		interface: String,
+		rx: String,
+		tx: String,
+	},
+	Interfaces(Vec<String>),
+	Auto,
+}
+
+impl Counters {
+	/// Read the names of the interfaces along with their combined received and
+	/// transmitted bytes, or `None` if there's no interface to read. Interfaces
+	/// which are absent (e.g. a USB adapter which was unplugged) are skipped.
+	fn read(&self) -> Option<(Vec<String>, u64, u64)> {
+		match self {
+			Counters::Paths { interface, rx, tx } => Some((
+				vec![interface.clone()],
+				read_counter(rx)?,
+				read_counter(tx)?,
+			)),
+			Counters::Interfaces(interfaces) => {
+				let (mut present, mut rx, mut tx) = (Vec::new(), 0, 0);
+				for interface in interfaces {
+					if let Some((rx_, tx_)) = read_interface(interface) {
+						present.push(interface.clone());
+						rx += rx_;
+						tx += tx_;
+					}
+				}
+				if present.is_empty() {
+					None
+				} else {
+					Some((present, rx, tx))
+				}
+			}
+			Counters::Auto => {
+				let interface = default_route(&fs::read_to_string(ROUTE_PATH).ok()?)?;
+				let (rx, tx) = read_interface(&interface)?;
+				Some((vec![interface], rx, tx))
+			}
+		}
+	}
+}
+
+/// The state of an interface's link. Wireless interfaces also have a link
+/// quality (as a percentage) and signal level (in dBm).
+struct Link {
+	up: bool,
+	wireless: Option<(f32, f32)>,
+}
+
+impl Link {
+	fn is_wireless(&self) -> bool {
+		self.wireless.is_some()
+	}
+
+	fn insert_values(&self, values: &mut Values) {
+		if let Some((quality, signal)) = self.wireless {
+			values.insert("quality".to_string(), quality.into());
+			values.insert("signal".to_string(), signal.into());
+		}
+	}
+}
+
+/// Read the state of an interface's link.
+fn read_link(interface: &str) -> Link {
+	let operstate = fs::read_to_string(format!("{}/{}/operstate", NET_DIR, interface));
+	Link {
+		// Interfaces which don't track their state (e.g. tunnels) report unknown.
+		up: matches!(
+			operstate.as_deref().map(str::trim),
+			Ok("up") | Ok("unknown")
+		),
+		wireless: fs::read_to_string(WIRELESS_PATH)
+			.ok()
+			.and_then(|x| parse_wireless(&x, interface)),
+	}
+}
+
+/// Get the link quality (as a percentage of the usual maximum of 70) and signal
+/// level of an interface from the contents of `/proc/net/wireless`.
+fn parse_wireless(contents: &str, interface: &str) -> Option<(f32, f32)> {
+	let line = contents
+		.lines()
+		.find(|x| x.trim_start().starts_with(&format!("{}:", interface)))?;
+	let mut fields = line.split(':').nth(1)?.split_whitespace().skip(1);
+	let mut parse = || fields.next()?.trim_end_matches('.').parse::<f32>().ok();
+	let (quality, signal) = (parse()?, parse()?);
+	Some(((quality * 100.0 / 70.0).min(100.0), signal))
 }
 
-impl Speed {
-	fn new(coef: f32) -> Speed {
-		Speed {
-			curr: 0.0,
-			prev: 0.0,
-			coef,
+/// Details of an interface which are slow to get, so are refreshed less often.
+#[derive(Default)]
+struct Details {
+	ssid: Option<String>,
+	ipv4: Option<String>,
+	ipv6: Option<String>,
+}
+
+impl Details {
+	fn insert_values(&self, values: &mut Values) {
+		for (key, value) in &[
+			("ssid", &self.ssid),
+			("ipv4", &self.ipv4),
+			("ipv6", &self.ipv6),
+		] {
+			if let Some(value) = value {
+				values.insert(key.to_string(), value.as_str().into());
+			}
 		}
 	}
+}
+
+/// Read the SSID (for wireless interfaces) and addresses of an interface.
+fn read_details(interface: &str, wireless: bool, ssid_command: &str) -> Details {
+	let run = |cmd: &mut Command| {
+		let output = cmd.output().ok()?;
+		Some(String::from_utf8_lossy(&output.stdout).to_string())
+	};
+	let ssid = if wireless {
+		let mut values = Values::new();
+		values.insert("interface".to_string(), interface.into());
+		let ssid_command = utils::format(ssid_command, &values);
+		run(Command::new("sh").args(["-c", &ssid_command])).and_then(|x| parse_ssid(&x))
+	} else {
+		None
+	};
+	let (ipv4, ipv6) = run(Command::new("ip").args(["-o", "addr", "show", "dev", interface]))
+		.map_or((None, None), |x| parse_addresses(&x));
+	Details { ssid, ipv4, ipv6 }
+}
+
+/// Get the SSID from the output of `iw`, which is given as either `SSID: name`
+/// or `ssid name` depending on the command.
+fn parse_ssid(output: &str) -> Option<String> {
+	lazy_static! {
+		static ref RE: Regex = Regex::new(r"(?mi)^\s*ssid:?\s+(.+?)\s*$").unwrap();
+	}
+	RE.captures(output).map(|x| x[1].to_string())
+}
+
+/// Get the first IPv4 address and the first IPv6 address (preferring global
+/// ones) from the output of `ip -o addr`.
+fn parse_addresses(output: &str) -> (Option<String>, Option<String>) {
+	lazy_static! {
+		static ref RE: Regex =
+			Regex::new(r"\binet(?P<v6>6)?\s+(?P<address>[^/\s]+)\S*.*?\bscope\s+(?P<scope>\w+)")
+				.unwrap();
+	}
+	let (mut ipv4, mut ipv6, mut global) = (None, None, false);
+	for caps in RE.captures_iter(output) {
+		let address = caps["address"].to_string();
+		if caps.name("v6").is_none() {
+			ipv4 = ipv4.or(Some(address));
+		} else if !global {
+			global = &caps["scope"] == "global";
+			if global || ipv6.is_none() {
+				ipv6 = Some(address);
+			}
+		}
+	}
+	(ipv4, ipv6)
+}
+
+/// Read a counter, which may be missing if its interface has gone away.
+fn read_counter(path: &str) -> Option<u64> {
+	fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Read the received and transmitted bytes of an interface.
+fn read_interface(interface: &str) -> Option<(u64, u64)> {
+	let path = |file| format!("{}/{}/statistics/{}", NET_DIR, interface, file);
+	Some((
+		read_counter(&path("rx_bytes"))?,
+		read_counter(&path("tx_bytes"))?,
+	))
+}
+
+/// Get the name of the interface a counter belongs to from its path, e.g.
+/// `/sys/class/net/wlan0/statistics/rx_bytes` belongs to `wlan0`.
+fn interface_of(path: &str) -> String {
+	Path::new(path)
+		.parent()
+		.and_then(|x| x.parent())
+		.and_then(|x| x.file_name())
+		.map_or_else(String::new, |x| x.to_string_lossy().to_string())
+}
+
+/// Get the interface of the default route with the lowest metric from the
+/// contents of `/proc/net/route`.
+fn default_route(contents: &str) -> Option<String> {
+	contents
+		.lines()
+		.skip(1)
+		.filter_map(|line| {
+			let fields: Vec<&str> = line.split_whitespace().collect();
+			let flags = u32::from_str_radix(fields.get(3)?, 16).ok()?;
+			let metric: u32 = fields.get(6)?.parse().ok()?;
+			let (destination, mask) = (*fields.get(1)?, *fields.get(7)?);
+			// Only routes which are up (RTF_UP) to 0.0.0.0/0.
+			if destination == "00000000" && mask == "00000000" && flags & 1 == 1 {
+				Some((metric, fields[0].to_string()))
+			} else {
+				None
+			}
+		})
+		.min()
+		.map(|(_, interface)| interface)
+}
+
+/// Traffic totals for a day, which are persisted across restarts.
+#[derive(Default, Serialize, Deserialize)]
+struct Daily {
+	date: String,
+	rx: u64,
+	tx: u64,
+}
+
+impl Daily {
+	/// Add traffic on `date`, starting afresh if it's a new day.
+	fn add(&mut self, date: &str, rx: u64, tx: u64) {
+		if self.date != date {
+			*self = Daily {
+				date: date.to_string(),
+				rx: 0,
+				tx: 0,
+			};
+		}
+		self.rx += rx;
+		self.tx += tx;
+	}
+}
+
+/// Save the daily totals of the block with the given name.
+fn save_daily(name: &str, daily: &Daily) {
+	if let Err(e) = state::save(name, daily) {
+		log::warn!("Could not save network totals: {:?}", e);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_default_route() {
+		let contents = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+eth0\t00000000\t0100A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0
+eth0\t0000A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0
+";
+		assert_eq!(default_route(contents), Some("eth0".to_string()));
+		assert_eq!(default_route(contents.lines().next().unwrap()), None);
+		assert_eq!(
+			default_route("Iface\nwlan0\t00000000\t0\t0003\t0\t0\t600\n"),
+			None
+		);
+	}
+
+	#[test]
+	fn test_parse_wireless() {
+		let contents = "\
+Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE
+ face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22
+wlp2s0: 0000   56.  -54.  -256        0      0      0      0     12        0
+";
+		assert_eq!(parse_wireless(contents, "wlp2s0"), Some((80.0, -54.0)));
+		assert_eq!(parse_wireless(contents, "eth0"), None);
+	}
+
+	#[test]
+	fn test_parse_ssid() {
+		let output =
+			"Connected to 00:11:22:33:44:55 (on wlan0)\n\tSSID: My Network\n\tfreq: 5180\n";
+		assert_eq!(parse_ssid(output), Some("My Network".to_string()));
+		assert_eq!(parse_ssid("Not connected.\n"), None);
+	}
+
+	#[test]
+	fn test_parse_addresses() {
+		let output = "\
+4: eth0    inet 192.0.2.2/24 brd 192.0.2.255 scope global eth0\\       valid_lft forever
+4: eth0    inet6 fe80::fc:ff:fe00:1/64 scope link \\       valid_lft forever
+4: eth0    inet6 fd00::2/64 scope global nodad \\       valid_lft forever
+";
+		assert_eq!(
+			parse_addresses(output),
+			(Some("192.0.2.2".to_string()), Some("fd00::2".to_string()))
+		);
+	}
 
-	fn push(&mut self, new: f32) {
-		self.prev = self.curr;
-		self.curr = new;
+	#[test]
+	fn test_daily() {
+		let mut daily = Daily::default();
+		daily.add("2021-01-01", 10, 20);
+		daily.add("2021-01-01", 1, 2);
+		assert_eq!((daily.rx, daily.tx), (11, 22));
+		daily.add("2021-01-02", 1, 2);
+		assert_eq!((daily.rx, daily.tx), (1, 2));
 	}
 
-	fn calc_speed(&self) -> f32 {
-		(self.curr - self.prev) * self.coef
+	#[test]
+	fn test_interface_of() {
+		assert_eq!(
+			interface_of("/sys/class/net/wlan0/statistics/rx_bytes"),
+			"wlan0"
+		);
 	}
 }
@@ -0,0 +1,305 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! # Temperature block
+//!
+//! Use this block to get temperature monitoring in the status bar.
+//!
+//! Sensors are found under `/sys/class/hwmon` (`temp*_input`) and
+//! `/sys/class/thermal` (`thermal_zone*/temp`). Thermal zones whose type is the
+//! name of a hwmon chip are skipped, since the kernel exposes those sensors in
+//! both places.
+//!
+//! Typical configuration:
+//!
+//! ```toml
+//! [temperature]
+//! ```
+//!
+//! ## Configuration options
+//!
+//! - `name`: Name of the block (must be unique)
+//! - `period`: Default update period in seconds (extra updates may occur on
+//!   event changes etc)
+//! - `chip`: Only use sensors of the chip with this name (e.g. `coretemp`, or
+//!   the type of a thermal zone such as `x86_pkg_temp`)
+//! - `label`: Only use sensors with this label (e.g. `Package id 0`, or the
+//!   name of a thermal zone such as `thermal_zone0`)
+//! - `summary`: How to summarise the sensors, either `max` (the default) or
+//!   `average`
+//! - `unit`: Either `celsius` (the default) or `fahrenheit`
+//! - `format`: Format of the block's text. Available placeholders are
+//!   `{temperature}` (the summary of the sensors), `{unit}` (`°C` or `°F`) and
+//!   the temperature of each sensor named after its chip and label, e.g.
+//!   `{coretemp_package_id_0}`
+//! - `warning`: Thresholds on the block's values above which it's shown as a
+//!   warning, e.g. `{temperature = 80}`. Defaults to each sensor's maximum
+//!   temperature according to the kernel (`temp*_max`)
+//! - `critical`: Thresholds on the block's values above which it's shown as
+//!   critical. Defaults to each sensor's critical temperature according to the
+//!   kernel (`temp*_crit`)
+//!
+//! For example, to show the CPU package temperature in Fahrenheit:
+//!
+//! ```toml
+//! [temperature]
+//! chip = "coretemp"
+//! label = "Package id 0"
+//! unit = "fahrenheit"
+//! ```
+
+use crate::blocks::{Block, Configure, Message, Sender, Values};
+use crate::hwmon::{self, Sensor};
+use crate::utils;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const THERMAL_DIR: &str = "/sys/class/thermal";
+
+#[derive(Configure, Deserialize)]
+pub struct Temperature {
+	#[serde(default = "default_name")]
+	name: String,
+	#[serde(default = "default_period")]
+	period: f32,
+	chip: Option<String>,
+	label: Option<String>,
+	#[serde(default)]
+	summary: Summary,
+	#[serde(default)]
+	unit: Unit,
+	#[serde(default = "default_format")]
+	format: String,
+	#[serde(default)]
+	warning: utils::Thresholds,
+	#[serde(default)]
+	critical: utils::Thresholds,
+}
+
+fn default_name() -> String {
+	"temperature".to_string()
+}
+
+fn default_period() -> f32 {
+	5.0
+}
+
+fn default_format() -> String {
+	"\u{f2c9} {temperature}{unit}".to_string()
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Summary {
+	#[default]
+	Max,
+	Average,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Unit {
+	#[default]
+	Celsius,
+	Fahrenheit,
+}
+
+impl Unit {
+	/// Convert a reading in millidegrees Celsius to this unit.
+	fn convert(self, millidegrees: f64) -> f64 {
+		let celsius = millidegrees / 1000.0;
+		match self {
+			Unit::Celsius => celsius,
+			Unit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+		}
+	}
+
+	fn symbol(self) -> &'static str {
+		match self {
+			Unit::Celsius => "°C",
+			Unit::Fahrenheit => "°F",
+		}
+	}
+}
+
+impl Temperature {
+	/// Get the sensors to monitor.
+	fn sensors(&self) -> anyhow::Result<Vec<Sensor>> {
+		let mut sensors = hwmon::sensors("temp");
+		let chips: Vec<String> = sensors.iter().map(|x| x.chip.clone()).collect();
+		sensors.extend(
+			thermal_zones(Path::new(THERMAL_DIR))
+				.into_iter()
+				.filter(|x| !chips.contains(&x.chip)),
+		);
+		sensors.retain(|x| x.matches(self.chip.as_deref(), self.label.as_deref()));
+		if sensors.is_empty() {
+			anyhow::bail!("No temperature sensors found");
+		}
+		Ok(sensors)
+	}
+}
+
+impl Sender for Temperature {
+	fn add_sender(&self, channel: crossbeam_channel::Sender<Message>) -> anyhow::Result<()> {
+		let name = self.get_name();
+		let format = self.format.clone();
+		let (summary, unit) = (self.summary, self.unit);
+		let period = Duration::from_secs_f32(self.period);
+		let sensors = self.sensors()?;
+		let warning = if self.warning.is_empty() {
+			kernel_thresholds(&sensors, unit, |x| x.max)
+		} else {
+			self.warning.clone()
+		};
+		let critical = if self.critical.is_empty() {
+			kernel_thresholds(&sensors, unit, |x| x.crit)
+		} else {
+			self.critical.clone()
+		};
+		let mut block = Block::new(name.clone(), true);
+
+		thread::spawn(move || loop {
+			let mut values = Values::new();
+			let mut readings = Vec::new();
+			for sensor in sensors.iter() {
+				if let Some(reading) = sensor.read() {
+					let temperature = unit.convert(reading);
+					values.insert(sensor.key(), temperature.into());
+					readings.push(temperature);
+				}
+			}
+			let temperature = match summarise(&readings, summary) {
+				Some(temperature) => temperature,
+				None => {
+					let error = "Failed to read any sensors".to_string();
+					channel.send(Message::error(block.clone(), error)).unwrap();
+					thread::sleep(period);
+					continue;
+				}
+			};
+			values.insert("temperature".to_string(), temperature.into());
+			values.insert("unit".to_string(), unit.symbol().into());
+			block.full_text = Some(utils::format(&format, &values));
+			let mut message = Message::new(block.clone());
+			message.state = utils::threshold_state(&values, &warning, &critical);
+			message.values = values;
+			channel.send(message).unwrap();
+			thread::sleep(period);
+		});
+
+		Ok(())
+	}
+}
+
+/// Summarise the readings of the sensors.
+fn summarise(readings: &[f64], summary: Summary) -> Option<f64> {
+	if readings.is_empty() {
+		return None;
+	}
+	Some(match summary {
+		Summary::Max => readings.iter().cloned().fold(f64::MIN, f64::max),
+		Summary::Average => readings.iter().sum::<f64>() / readings.len() as f64,
+	})
+}
+
+/// Get thresholds on each sensor from the limits the kernel gives, ignoring
+/// nonsensical (non-positive) limits.
+fn kernel_thresholds(
+	sensors: &[Sensor],
+	unit: Unit,
+	limit: fn(&Sensor) -> Option<f64>,
+) -> utils::Thresholds {
+	sensors
+		.iter()
+		.filter_map(|x| {
+			let limit = limit(x).filter(|x| *x > 0.0)?;
+			Some((x.key(), unit.convert(limit)))
+		})
+		.collect()
+}
+
+/// Find the thermal zones in `dir`. Each zone is named after its directory and
+/// belongs to a "chip" named after its type, with limits taken from its hot and
+/// critical trip points.
+fn thermal_zones(dir: &Path) -> Vec<Sensor> {
+	let mut zones: Vec<PathBuf> = fs::read_dir(dir)
+		.map(|x| x.filter_map(|x| x.ok()).map(|x| x.path()).collect())
+		.unwrap_or_default();
+	zones.retain(|x| x.join("temp").exists());
+	zones.sort_by_key(|x| {
+		let name = x.file_name().unwrap().to_string_lossy().to_string();
+		let number: u32 = name.trim_start_matches("thermal_zone").parse().unwrap_or(0);
+		number
+	});
+	zones
+		.iter()
+		.map(|zone| {
+			let trip_point = |kind: &str| {
+				(0..)
+					.map(|i| zone.join(format!("trip_point_{}_type", i)))
+					.take_while(|x| x.exists())
+					.enumerate()
+					.find(|(_, x)| fs::read_to_string(x).is_ok_and(|x| x.trim() == kind))
+					.and_then(|(i, _)| {
+						hwmon::read_number(&zone.join(format!("trip_point_{}_temp", i)))
+					})
+			};
+			Sensor {
+				chip: fs::read_to_string(zone.join("type"))
+					.map_or_else(|_| "thermal".to_string(), |x| x.trim().to_string()),
+				label: zone.file_name().unwrap().to_string_lossy().to_string(),
+				input: zone.join("temp"),
+				max: trip_point("hot"),
+				crit: trip_point("critical"),
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn convert_units() {
+		assert_eq!(Unit::Celsius.convert(45500.0), 45.5);
+		assert_eq!(Unit::Fahrenheit.convert(100000.0), 212.0);
+	}
+
+	#[test]
+	fn summarise_readings() {
+		assert_eq!(summarise(&[40.0, 60.0, 50.0], Summary::Max), Some(60.0));
+		assert_eq!(summarise(&[40.0, 60.0, 50.0], Summary::Average), Some(50.0));
+		assert_eq!(summarise(&[], Summary::Max), None);
+	}
+
+	#[test]
+	fn find_thermal_zones() {
+		let tmp = tempfile::tempdir().unwrap();
+		let dir = tmp.path();
+		let zone = dir.join("thermal_zone0");
+		fs::create_dir_all(&zone).unwrap();
+		fs::create_dir_all(dir.join("cooling_device0")).unwrap();
+		fs::write(zone.join("type"), "acpitz\n").unwrap();
+		fs::write(zone.join("temp"), "50000\n").unwrap();
+		fs::write(zone.join("trip_point_0_type"), "passive\n").unwrap();
+		fs::write(zone.join("trip_point_0_temp"), "90000\n").unwrap();
+		fs::write(zone.join("trip_point_1_type"), "critical\n").unwrap();
+		fs::write(zone.join("trip_point_1_temp"), "105000\n").unwrap();
+
+		let zones = thermal_zones(dir);
+		assert_eq!(zones.len(), 1);
+		assert_eq!(zones[0].key(), "acpitz_thermal_zone0");
+		assert_eq!(zones[0].read(), Some(50000.0));
+		assert_eq!(zones[0].max, None);
+		assert_eq!(zones[0].crit, Some(105000.0));
+		let critical = kernel_thresholds(&zones, Unit::Celsius, |x| x.crit);
+		assert_eq!(critical["acpitz_thermal_zone0"], 105.0);
+	}
+}
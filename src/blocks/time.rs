@@ -17,7 +17,7 @@
 //!
 //! - `name`: Name of the block (must be unique)
 //! - `period`: Default update period in seconds (extra updates may occur on
-//!    event changes etc)
+//!   event changes etc)
 //! - `format`: Strftime format string for specifying the time format
 
 use crate::blocks::{Block, Configure, Message, Sender};
@@ -50,14 +50,13 @@ fn default_format() -> String {
 
 impl Sender for Time {
 	fn add_sender(&self, channel: crossbeam_channel::Sender<Message>) -> anyhow::Result<()> {
-		let name = self.get_name();
 		let format = self.format.clone();
 		let period = self.period;
-		let mut block = Block::new(name.clone(), true);
+		let mut block = Block::new(self.get_name(), true);
 
 		thread::spawn(move || loop {
 			block.full_text = Some(Local::now().format(&format).to_string());
-			channel.send((name.clone(), block.to_string())).unwrap();
+			channel.send(Message::new(block.clone())).unwrap();
 			thread::sleep(Duration::from_secs_f32(period));
 		});
 
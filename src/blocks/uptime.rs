@@ -0,0 +1,198 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! # Uptime block
+//!
+//! Use this block to get the time since boot in the status bar.
+//!
+//! This block reads from `/proc/uptime`, which counts time spent suspended
+//! (`CLOCK_BOOTTIME`). To only count the time the machine has been awake
+//! (`CLOCK_MONOTONIC`) set `include_suspend` to false.
+//!
+//! Typical configuration:
+//!
+//! ```toml
+//! [uptime]
+//! ```
+//!
+//! ## Configuration options
+//!
+//! - `name`: Name of the block (must be unique)
+//! - `period`: Default update period in seconds (extra updates may occur on
+//!   event changes etc)
+//! - `include_suspend`: Whether the uptime counts time spent suspended
+//! - `format`: Format of the block's text. Available placeholders are
+//!   `{uptime}` (e.g. `3d 04h`) and `{boot}` (the time of the last boot)
+//! - `boot_format`: Strftime format string for the time of the last boot
+//!
+//! For example, to show when the machine was booted:
+//!
+//! ```toml
+//! [uptime]
+//! format = "up {uptime} since {boot}"
+//! boot_format = "%a %H:%M"
+//! ```
+
+use crate::blocks::{Block, Configure, Message, Sender, Values};
+use crate::utils;
+use chrono::format::{Item, StrftimeItems};
+use chrono::prelude::*;
+use serde::Deserialize;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+const PATH: &str = "/proc/uptime";
+
+#[derive(Configure, Deserialize)]
+pub struct Uptime {
+	#[serde(default = "default_name")]
+	name: String,
+	#[serde(default = "default_period")]
+	period: f32,
+	#[serde(default = "default_include_suspend")]
+	include_suspend: bool,
+	#[serde(default = "default_format")]
+	format: String,
+	#[serde(default = "default_boot_format")]
+	boot_format: String,
+}
+
+fn default_name() -> String {
+	"uptime".to_string()
+}
+
+fn default_period() -> f32 {
+	60.0
+}
+
+fn default_include_suspend() -> bool {
+	true
+}
+
+fn default_format() -> String {
+	"\u{f2f2} {uptime}".to_string()
+}
+
+fn default_boot_format() -> String {
+	"%Y-%m-%d %H:%M".to_string()
+}
+
+impl Sender for Uptime {
+	fn add_sender(&self, channel: crossbeam_channel::Sender<Message>) -> anyhow::Result<()> {
+		let name = self.get_name();
+		let format = self.format.clone();
+		let include_suspend = self.include_suspend;
+		let period = Duration::from_secs_f32(self.period);
+		check_strftime(&self.boot_format)?;
+		let since_boot = read_uptime()?;
+		let boot = Local::now() - chrono::Duration::milliseconds((since_boot * 1000.0) as i64);
+		let boot = boot.format(&self.boot_format).to_string();
+		let mut block = Block::new(name.clone(), true);
+
+		thread::spawn(move || loop {
+			let uptime = if include_suspend {
+				read_uptime().ok()
+			} else {
+				monotonic()
+			};
+			let mut values = Values::new();
+			values.insert("boot".to_string(), boot.as_str().into());
+			if let Some(uptime) = uptime {
+				values.insert("uptime".to_string(), format_duration(uptime).into());
+				values.insert("uptime_secs".to_string(), uptime.into());
+			}
+			block.full_text = Some(utils::format(&format, &values));
+			let mut message = Message::new(block.clone());
+			message.values = values;
+			channel.send(message).unwrap();
+			thread::sleep(period);
+		});
+
+		Ok(())
+	}
+}
+
+/// Check a strftime format string is valid, since formatting a time with an
+/// invalid one panics.
+fn check_strftime(format: &str) -> anyhow::Result<()> {
+	if StrftimeItems::new(format).any(|x| matches!(x, Item::Error)) {
+		anyhow::bail!("Invalid strftime format '{}'", format);
+	}
+	Ok(())
+}
+
+/// Read the seconds since boot, including time spent suspended.
+fn read_uptime() -> anyhow::Result<f64> {
+	let contents = fs::read_to_string(PATH)?;
+	parse(&contents).ok_or_else(|| anyhow::anyhow!("Failed to parse '{}'", PATH))
+}
+
+/// Parse the contents of `/proc/uptime`, e.g. `2504.69 1953.96` (the seconds
+/// since boot and seconds spent idle summed over cores).
+fn parse(contents: &str) -> Option<f64> {
+	contents.split_whitespace().next()?.parse().ok()
+}
+
+/// Get the seconds since boot, excluding time spent suspended.
+fn monotonic() -> Option<f64> {
+	let mut time = libc::timespec {
+		tv_sec: 0,
+		tv_nsec: 0,
+	};
+	if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) } != 0 {
+		return None;
+	}
+	Some(time.tv_sec as f64 + time.tv_nsec as f64 / 1e9)
+}
+
+/// Format a number of seconds with its two largest units, e.g. `3d 04h` or
+/// `2h 05m`.
+fn format_duration(secs: f64) -> String {
+	let minutes = (secs / 60.0) as u64;
+	let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+	if days > 0 {
+		format!("{}d {:02}h", days, hours)
+	} else if hours > 0 {
+		format!("{}h {:02}m", hours, minutes)
+	} else {
+		format!("{}m", minutes)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_uptime() {
+		assert_eq!(parse("2504.69 1953.96\n"), Some(2504.69));
+		assert_eq!(parse(""), None);
+	}
+
+	#[test]
+	fn durations() {
+		assert_eq!(
+			format_duration(3.0 * 86400.0 + 4.0 * 3600.0 + 59.0),
+			"3d 04h"
+		);
+		assert_eq!(format_duration(2.0 * 3600.0 + 5.0 * 60.0), "2h 05m");
+		assert_eq!(format_duration(59.0), "0m");
+	}
+
+	#[test]
+	fn strftime_formats() {
+		assert!(check_strftime(&default_boot_format()).is_ok());
+		assert!(check_strftime("%a %H:%M").is_ok());
+		assert!(check_strftime("%Q").is_err());
+	}
+
+	#[test]
+	fn monotonic_clock() {
+		let awake = monotonic().unwrap();
+		assert!(awake > 0.0);
+		assert!(awake <= read_uptime().unwrap() + 1.0);
+	}
+}
@@ -17,9 +17,9 @@
 //!
 //! - `name`: Name of the block (must be unique)
 //! - `period`: Default update period in seconds (extra updates may occur on
-//!    event changes etc)
+//!   event changes etc)
 //! - `update_signal`: Used to define what signal to listen on for immediate
-//!    retriggering of updates
+//!   retriggering of updates
 
 use crate::blocks::{Block, Configure, Message, Sender};
 use crate::utils;
@@ -50,15 +50,17 @@ fn default_update_signal() -> i32 {
 
 impl Sender for Volume {
 	fn add_sender(&self, s: crossbeam_channel::Sender<Message>) -> anyhow::Result<()> {
-		let name = self.get_name();
 		let re = regex::Regex::new(r"(?P<mute>\d)\n(?P<volume>\d+)").unwrap();
 		let mut block = Block::new(self.name.clone(), true);
-		let mut monitor = utils::monitor_command("pulsemixer", &["--get-mute", "--get-volume"], self.period);
+		let mut monitor =
+			utils::monitor_command("pulsemixer", &["--get-mute", "--get-volume"], self.period);
 		let recv = utils::wait_for_signal(self.update_signal, self.period);
 
 		thread::spawn(move || loop {
 			let output = monitor.read();
+			let mut percentage = None;
 			block.full_text = Some(if let Some(captures) = re.captures(&output) {
+				percentage = captures.name("volume").unwrap().as_str().parse().ok();
 				if captures.name("mute").unwrap().as_str() == "0" {
 					format!(" {}%", captures.name("volume").unwrap().as_str())
 				} else {
@@ -67,7 +69,9 @@ impl Sender for Volume {
 			} else {
 				output
 			});
-			s.send((name.clone(), block.to_string())).unwrap();
+			let mut message = Message::new(block.clone());
+			message.percentage = percentage;
+			s.send(message).unwrap();
 			recv.recv().unwrap();
 		});
 
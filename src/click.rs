@@ -0,0 +1,85 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! Click events for blocks.
+//!
+//! Clicks are either read from stdin (see the click events section of the
+//! [i3bar protocol](https://i3wm.org/docs/i3bar-protocol.html)) or simulated
+//! by the preview output. A block which wants to react to clicks should call
+//! `listen` with its name, clicks for blocks which aren't listening are
+//! ignored.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+use std::sync::Mutex;
+use std::thread;
+
+pub const LEFT: u8 = 1;
+pub const MIDDLE: u8 = 2;
+pub const RIGHT: u8 = 3;
+pub const SCROLL_UP: u8 = 4;
+pub const SCROLL_DOWN: u8 = 5;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Click {
+	pub name: String,
+	pub button: u8,
+}
+
+lazy_static! {
+	static ref LISTENERS: Mutex<HashMap<String, crossbeam_channel::Sender<Click>>> =
+		Mutex::new(HashMap::new());
+}
+
+/// Listen for clicks on the block with the given name.
+pub fn listen(name: &str) -> crossbeam_channel::Receiver<Click> {
+	let (s, r) = crossbeam_channel::unbounded();
+	LISTENERS.lock().unwrap().insert(name.to_string(), s);
+	r
+}
+
+/// Send a click to the block it is for.
+pub fn dispatch(click: Click) {
+	log::debug!("{:?}", click);
+	if let Some(s) = LISTENERS.lock().unwrap().get(&click.name) {
+		s.send(click).unwrap();
+	}
+}
+
+/// Parse a line of the i3bar click event stream. This is an infinite JSON
+/// array with one event per line, so each line may begin with `[` or `,`.
+fn parse_line(line: &str) -> Option<Click> {
+	let line = line.trim().trim_start_matches(&['[', ','][..]);
+	serde_json::from_str(line).ok()
+}
+
+/// Read click events from stdin and dispatch them.
+pub fn read_stdin() {
+	thread::spawn(|| {
+		for line in io::stdin().lock().lines() {
+			if let Some(click) = parse_line(&line.unwrap()) {
+				dispatch(click);
+			}
+		}
+	});
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parse_click_events() {
+		assert_eq!(parse_line("["), None);
+		assert_eq!(
+			parse_line(r#",{"name":"battery","instance":"","button":3,"x":10,"y":2}"#),
+			Some(Click {
+				name: "battery".to_string(),
+				button: RIGHT
+			})
+		);
+	}
+}
@@ -0,0 +1,150 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! Hardware monitoring sensors.
+//!
+//! Each chip the kernel monitors (e.g. `coretemp` or `thinkpad`) has a directory
+//! in `/sys/class/hwmon` containing its name and a set of files per sensor, e.g.
+//! `temp1_input`, `temp1_label` and `temp1_crit`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const HWMON_DIR: &str = "/sys/class/hwmon";
+
+/// A single sensor of a chip.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sensor {
+	/// Name of the chip the sensor belongs to.
+	pub chip: String,
+	/// Label of the sensor, falling back to its file prefix (e.g. `temp1`).
+	pub label: String,
+	/// Path to the file giving the sensor's reading.
+	pub input: PathBuf,
+	/// The highest reading the sensor should normally give, if known.
+	pub max: Option<f64>,
+	/// The reading above which the hardware is in danger, if known.
+	pub crit: Option<f64>,
+}
+
+impl Sensor {
+	/// Get the current reading of the sensor in the kernel's units (e.g.
+	/// millidegrees Celsius or RPM).
+	pub fn read(&self) -> Option<f64> {
+		read_number(&self.input)
+	}
+
+	/// Get a name for the sensor which is usable as a placeholder, e.g.
+	/// `coretemp_package_id_0`.
+	pub fn key(&self) -> String {
+		format!("{}_{}", self.chip, self.label)
+			.chars()
+			.map(|x| {
+				if x.is_ascii_alphanumeric() {
+					x.to_ascii_lowercase()
+				} else {
+					'_'
+				}
+			})
+			.collect()
+	}
+
+	/// Whether the sensor matches the given chip and label, where either matches
+	/// anything when not given.
+	pub fn matches(&self, chip: Option<&str>, label: Option<&str>) -> bool {
+		chip.is_none_or(|x| x == self.chip) && label.is_none_or(|x| x == self.label)
+	}
+}
+
+/// Find all sensors of the given kind (e.g. `temp` or `fan`).
+pub fn sensors(kind: &str) -> Vec<Sensor> {
+	sensors_in(Path::new(HWMON_DIR), kind)
+}
+
+fn sensors_in(dir: &Path, kind: &str) -> Vec<Sensor> {
+	let mut chips: Vec<PathBuf> = fs::read_dir(dir)
+		.map(|x| x.filter_map(|x| x.ok()).map(|x| x.path()).collect())
+		.unwrap_or_default();
+	chips.sort_by_key(|x| numeric_suffix(x));
+	let mut sensors = Vec::new();
+	for chip_dir in chips {
+		let chip = fs::read_to_string(chip_dir.join("name"))
+			.map(|x| x.trim().to_string())
+			.unwrap_or_else(|_| file_name(&chip_dir));
+		let mut inputs: Vec<PathBuf> = fs::read_dir(&chip_dir)
+			.map(|x| x.filter_map(|x| x.ok()).map(|x| x.path()).collect())
+			.unwrap_or_default();
+		inputs.retain(|x| {
+			let name = file_name(x);
+			name.starts_with(kind) && name.ends_with("_input")
+		});
+		inputs.sort_by_key(|x| numeric_suffix(x));
+		for input in inputs {
+			let name = file_name(&input);
+			let prefix = name.trim_end_matches("_input");
+			let attribute = |x: &str| chip_dir.join(format!("{}_{}", prefix, x));
+			sensors.push(Sensor {
+				chip: chip.clone(),
+				label: fs::read_to_string(attribute("label"))
+					.map(|x| x.trim().to_string())
+					.unwrap_or_else(|_| prefix.to_string()),
+				max: read_number(&attribute("max")),
+				crit: read_number(&attribute("crit")),
+				input,
+			});
+		}
+	}
+	sensors
+}
+
+/// Read a number from a sysfs file.
+pub fn read_number(path: &Path) -> Option<f64> {
+	fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn file_name(path: &Path) -> String {
+	path.file_name()
+		.map_or_else(String::new, |x| x.to_string_lossy().to_string())
+}
+
+/// Get the number at the end of a path's name (e.g. 10 for `hwmon10` or
+/// `temp10_input`), so that paths sort numerically.
+fn numeric_suffix(path: &Path) -> (u32, String) {
+	let name = file_name(path);
+	let name = name.trim_end_matches("_input");
+	let digits = name.trim_start_matches(|x: char| !x.is_ascii_digit());
+	(digits.parse().unwrap_or(0), name.to_string())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn find_sensors() {
+		let tmp = tempfile::tempdir().unwrap();
+		let dir = tmp.path();
+		let chip = dir.join("hwmon0");
+		fs::create_dir_all(&chip).unwrap();
+		fs::write(chip.join("name"), "coretemp\n").unwrap();
+		fs::write(chip.join("temp1_input"), "45000\n").unwrap();
+		fs::write(chip.join("temp1_label"), "Package id 0\n").unwrap();
+		fs::write(chip.join("temp1_crit"), "100000\n").unwrap();
+		fs::write(chip.join("temp10_input"), "40000\n").unwrap();
+		fs::write(chip.join("temp2_input"), "42000\n").unwrap();
+		fs::write(chip.join("fan1_input"), "2000\n").unwrap();
+
+		let sensors = sensors_in(dir, "temp");
+		let labels: Vec<&str> = sensors.iter().map(|x| x.label.as_str()).collect();
+		assert_eq!(labels, ["Package id 0", "temp2", "temp10"]);
+		assert_eq!(sensors[0].key(), "coretemp_package_id_0");
+		assert_eq!(sensors[0].read(), Some(45000.0));
+		assert_eq!(sensors[0].crit, Some(100000.0));
+		assert_eq!(sensors[0].max, None);
+		assert!(sensors[1].matches(Some("coretemp"), None));
+		assert!(!sensors[1].matches(None, Some("Package id 0")));
+		assert_eq!(sensors_in(dir, "fan").len(), 1);
+	}
+}
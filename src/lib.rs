@@ -16,5 +16,12 @@ extern crate lazy_static;
 
 pub mod args;
 pub mod blocks;
+pub mod click;
 pub mod ema;
+pub mod hwmon;
+pub mod output;
+pub mod pressure;
+pub mod rate;
+pub mod shutdown;
+pub mod state;
 pub mod utils;
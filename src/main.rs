@@ -4,14 +4,14 @@
 // distributed except according to those terms
 
 use anyhow::Context;
-use rs_blocks::args;
 use rs_blocks::blocks::{
-	battery, brightness, cpu, memory, network, time, volume, Configure, Sender,
+	battery, brightness, cpu, disk, diskio, fan, load, memory, network, temperature, time, uptime,
+	volume, Configure, Sender,
 };
-use std::collections::HashMap;
+use rs_blocks::{args, click, output, shutdown};
 use std::fs;
 
-const DEFAULT_CONFIG: &'static str = r#"
+const DEFAULT_CONFIG: &str = r#"
 [time]
 format = "%a %d %b <b>%H:%M:%S</b>"
 period = 1
@@ -27,23 +27,44 @@ fn main() -> anyhow::Result<()> {
 		DEFAULT_CONFIG.to_string()
 	};
 
+	let mut senders = Vec::new();
+	for (block_type, config) in parse_config(&config) {
+		senders.push(create_sender(&block_type, config.to_string())?);
+	}
+
+	if let Some(name) = &cmd_args.waybar {
+		senders.retain(|x| &x.get_name() == name);
+		if senders.is_empty() {
+			anyhow::bail!("No block named '{}' found in config", name);
+		}
+	}
+
+	shutdown::on_signals()?;
 	let (s, r) = crossbeam_channel::unbounded();
 	let mut order = Vec::new();
 
-	for (block_type, config) in parse_config(&config) {
-		let sender = create_sender(&block_type, config.to_string())?;
+	for sender in senders {
 		order.push(sender.get_name());
 		sender.add_sender(s.clone())?;
 	}
+	let r = output::metrics::tee(
+		r,
+		cmd_args.metrics_address.as_deref(),
+		cmd_args.metrics_file.clone(),
+	)?;
 
-	let mut blocks = HashMap::new();
-	println!("{{\"version\":1,\"click_events\":true}}");
-	println!("[");
-	loop {
-		let (name, block) = r.recv().unwrap();
-		blocks.insert(name, block);
-		print_blocks(&blocks, &order);
-	}
+	let result = if cmd_args.waybar.is_some() {
+		output::waybar::publish(r)
+	} else if cmd_args.preview {
+		output::preview::publish(r, &order)
+	} else if cmd_args.output.as_deref() == Some("jsonl") {
+		output::jsonl::publish(r)
+	} else {
+		click::read_stdin();
+		output::i3bar::publish(r, &order)
+	};
+	shutdown::run();
+	result
 }
 
 /// Create a sender object for a given config.
@@ -52,9 +73,15 @@ fn create_sender(name: &str, config: String) -> anyhow::Result<Box<dyn Sender>>
 		"battery" => Ok(Box::new(battery::Battery::new(&config)?)),
 		"brightness" => Ok(Box::new(brightness::Brightness::new(&config)?)),
 		"cpu" => Ok(Box::new(cpu::Cpu::new(&config)?)),
+		"disk" => Ok(Box::new(disk::Disk::new(&config)?)),
+		"diskio" => Ok(Box::new(diskio::Diskio::new(&config)?)),
+		"fan" => Ok(Box::new(fan::Fan::new(&config)?)),
+		"load" => Ok(Box::new(load::Load::new(&config)?)),
 		"memory" => Ok(Box::new(memory::Memory::new(&config)?)),
 		"network" => Ok(Box::new(network::Network::new(&config)?)),
+		"temperature" => Ok(Box::new(temperature::Temperature::new(&config)?)),
 		"time" => Ok(Box::new(time::Time::new(&config)?)),
+		"uptime" => Ok(Box::new(uptime::Uptime::new(&config)?)),
 		"volume" => Ok(Box::new(volume::Volume::new(&config)?)),
 		_ => {
 			anyhow::bail!("Unrecognised config element '{}'", name)
@@ -63,26 +90,50 @@ fn create_sender(name: &str, config: String) -> anyhow::Result<Box<dyn Sender>>
 }
 
 /// Return a name->body mapping of a config file. Config file must be in toml
-/// format with only top-level tables.
+/// format with only top-level tables, each with its header on its own line
+/// (optionally followed by a comment).
 fn parse_config(cfg: &str) -> Vec<(String, String)> {
-	let re = regex::Regex::new(r"\[(?P<name>\w+)\]\s+(?P<body>[^\[]*|\z)").unwrap();
-	re.captures_iter(cfg)
-		.map(|x| (x["name"].to_string(), x["body"].to_string()))
+	let re = regex::Regex::new(r"(?m)^\s*\[(?P<name>\w+)\]\s*(#.*)?$").unwrap();
+	let headers: Vec<_> = re.captures_iter(cfg).collect();
+	headers
+		.iter()
+		.enumerate()
+		.map(|(i, caps)| {
+			let start = caps.get(0).unwrap().end();
+			let end = headers
+				.get(i + 1)
+				.map_or(cfg.len(), |x| x.get(0).unwrap().start());
+			(caps["name"].to_string(), cfg[start..end].to_string())
+		})
 		.collect()
 }
 
-/// Print all blocks in a JSON array.
-fn print_blocks(blocks: &HashMap<String, String>, order: &[String]) {
-	let mut first = true;
-	print!("[");
-	for name in order.iter() {
-		if let Some(block) = blocks.get(name) {
-			if !first {
-				print!(",");
-			}
-			print!("{}", block);
-			first = false;
-		}
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parse_config_with_arrays() {
+		let config = parse_config("[time]\nperiod = 1\n\n[battery]\nbatteries = [\"BAT0\"]\n");
+		assert_eq!(config.len(), 2);
+		assert_eq!(
+			config[0],
+			("time".to_string(), "\nperiod = 1\n".to_string())
+		);
+		assert_eq!(config[1].0, "battery");
+		assert_eq!(config[1].1.trim(), "batteries = [\"BAT0\"]");
+	}
+
+	#[test]
+	fn parse_config_with_comments() {
+		let config = parse_config(
+			"[cpu] # usage
+period = 1
+[time]
+",
+		);
+		assert_eq!(config.len(), 2);
+		assert_eq!(config[0], ("cpu".to_string(), "\nperiod = 1\n".to_string()));
+		assert_eq!(config[1].0, "time");
 	}
-	println!("],");
 }
@@ -0,0 +1,53 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! Output for the [i3bar protocol](https://i3wm.org/docs/i3bar-protocol.html).
+
+use crate::blocks::{Block, Message, State};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Publish all blocks received on `r` as an infinite JSON array of status
+/// lines, with blocks appearing in the given order. Blocks in a critical or
+/// error state are marked as urgent.
+pub fn publish(r: crossbeam_channel::Receiver<Message>, order: &[String]) -> anyhow::Result<()> {
+	let mut blocks = HashMap::new();
+	let stdout = io::stdout();
+	super::until_closed(|| {
+		let mut out = stdout.lock();
+		writeln!(out, "{{\"version\":1,\"click_events\":true}}")?;
+		writeln!(out, "[")?;
+		drop(out);
+		for message in r.iter() {
+			let mut block = message.block;
+			if matches!(message.state, Some(State::Critical) | Some(State::Error)) {
+				block.urgent = Some(true);
+			}
+			blocks.insert(block.name.clone(), block);
+			print_blocks(&mut stdout.lock(), &blocks, order)?;
+		}
+		Ok(())
+	})
+}
+
+/// Print all blocks in a JSON array.
+fn print_blocks(
+	out: &mut impl Write,
+	blocks: &HashMap<String, Block>,
+	order: &[String],
+) -> io::Result<()> {
+	let mut first = true;
+	write!(out, "[")?;
+	for name in order.iter() {
+		if let Some(block) = blocks.get(name) {
+			if !first {
+				write!(out, ",")?;
+			}
+			write!(out, "{}", block)?;
+			first = false;
+		}
+	}
+	writeln!(out, "],")
+}
@@ -0,0 +1,75 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! Output of one JSON object per line for each block update, for use in
+//! scripts. Each object contains the block's name, rendered text, the raw
+//! values the text was rendered from, why the block failed to update (if it
+//! did) and a unix timestamp, e.g.:
+//!
+//! ```json
+//! {"name":"cpu","text":" 12.5%","values":{"usage":12.5},"timestamp":1634567890.1}
+//! ```
+
+use crate::blocks::{Message, Values};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::time::UNIX_EPOCH;
+
+#[derive(Serialize)]
+struct Output {
+	name: String,
+	text: String,
+	values: Values,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	error: Option<String>,
+	timestamp: f64,
+}
+
+/// Encode a message as a line of JSON.
+fn encode(message: Message) -> anyhow::Result<String> {
+	let output = Output {
+		name: message.block.name,
+		text: message.block.full_text.unwrap_or_default(),
+		values: message.values,
+		error: message.error,
+		timestamp: message.timestamp.duration_since(UNIX_EPOCH)?.as_secs_f64(),
+	};
+	Ok(serde_json::to_string(&output)?)
+}
+
+/// Publish each block received on `r` as a line of JSON.
+pub fn publish(r: crossbeam_channel::Receiver<Message>) -> anyhow::Result<()> {
+	let stdout = io::stdout();
+	super::until_closed(|| {
+		for message in r.iter() {
+			writeln!(stdout.lock(), "{}", encode(message)?)?;
+		}
+		Ok(())
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::blocks::Block;
+	use std::time::Duration;
+
+	#[test]
+	fn message_to_line() {
+		let mut block = Block::new("cpu".to_string(), true);
+		block.full_text = Some("12.5%".to_string());
+		let mut message = Message::new(block);
+		message.values.insert("usage".to_string(), 12.5.into());
+		message
+			.values
+			.insert("governor".to_string(), "powersave".into());
+		message.timestamp = UNIX_EPOCH + Duration::from_millis(1634567890100);
+
+		assert_eq!(
+			encode(message).unwrap(),
+			r#"{"name":"cpu","text":"12.5%","values":{"governor":"powersave","usage":12.5},"timestamp":1634567890.1}"#
+		);
+	}
+}
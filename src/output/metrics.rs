@@ -0,0 +1,184 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! Export the values computed by blocks as Prometheus metrics.
+//!
+//! Metrics can be served over HTTP (`--metrics-address`) and/or written to a
+//! file for node exporter's textfile collector (`--metrics-file`). Each value
+//! a block computes is exported as a gauge named `rs_blocks_<value>` with a
+//! `block` label, so the same value from several blocks forms one family, e.g.:
+//!
+//! ```txt
+//! # TYPE rs_blocks_usage gauge
+//! rs_blocks_usage{block="cpu"} 12.5
+//! rs_blocks_usage{block="memory"} 54
+//! ```
+
+use crate::blocks::{Message, Value, Values};
+use anyhow::Context;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait on a client before giving up on it, so that a client which
+/// never sends its request can't stall every later scrape.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type Store = Arc<Mutex<BTreeMap<String, Values>>>;
+
+/// Record the values of all messages received on `r`, forwarding the messages
+/// to the returned receiver. If neither an address nor a path are given then
+/// `r` is returned as is.
+pub fn tee(
+	r: crossbeam_channel::Receiver<Message>,
+	address: Option<&str>,
+	path: Option<PathBuf>,
+) -> anyhow::Result<crossbeam_channel::Receiver<Message>> {
+	if address.is_none() && path.is_none() {
+		return Ok(r);
+	}
+
+	let store = Store::default();
+	if let Some(address) = address {
+		serve(address, store.clone())?;
+	}
+
+	let (s, r2) = crossbeam_channel::unbounded();
+	thread::spawn(move || {
+		for message in r.iter() {
+			let name = message.name().to_string();
+			store.lock().unwrap().insert(name, message.values.clone());
+			if let Some(path) = &path {
+				if let Err(e) = write(path, &encode(&store.lock().unwrap())) {
+					log::error!("{:?}", e);
+				}
+			}
+			s.send(message).unwrap();
+		}
+	});
+
+	Ok(r2)
+}
+
+/// Serve the current values over HTTP at the given address.
+fn serve(address: &str, store: Store) -> anyhow::Result<()> {
+	let listener =
+		TcpListener::bind(address).context(format!("Failed to bind to '{}'", address))?;
+
+	thread::spawn(move || {
+		for mut stream in listener.incoming().flatten() {
+			let _ = stream.set_read_timeout(Some(TIMEOUT));
+			let _ = stream.set_write_timeout(Some(TIMEOUT));
+			// The request itself is irrelevant, all paths serve the metrics
+			let _ = stream.read(&mut [0; 1024]);
+			let body = encode(&store.lock().unwrap());
+			let response = format!(
+				"HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+				body.len(),
+				body
+			);
+			if let Err(e) = stream.write_all(response.as_bytes()) {
+				log::warn!("Failed to write metrics response: {}", e);
+			}
+		}
+	});
+
+	Ok(())
+}
+
+/// Atomically write `contents` to `path`, so the textfile collector never reads
+/// a partially written file.
+fn write(path: &Path, contents: &str) -> anyhow::Result<()> {
+	let tmp = path.with_extension("tmp");
+	fs::write(&tmp, contents).context(format!("Failed to write '{}'", tmp.display()))?;
+	fs::rename(&tmp, path).context(format!("Failed to write '{}'", path.display()))
+}
+
+/// Encode numeric values in the Prometheus text format.
+fn encode(store: &BTreeMap<String, Values>) -> String {
+	let mut families: BTreeMap<String, Vec<(&str, f64)>> = BTreeMap::new();
+	for (block, values) in store.iter() {
+		for (key, value) in values.iter() {
+			if let Value::Number(value) = value {
+				let metric = sanitise(&format!("rs_blocks_{}", key));
+				families.entry(metric).or_default().push((block, *value));
+			}
+		}
+	}
+
+	let mut out = String::new();
+	for (metric, samples) in families.iter() {
+		out.push_str(&format!("# TYPE {} gauge\n", metric));
+		for (block, value) in samples {
+			out.push_str(&format!(
+				"{}{{block=\"{}\"}} {}\n",
+				metric,
+				escape(block),
+				format_value(*value)
+			));
+		}
+	}
+	out
+}
+
+/// Escape a label value.
+fn escape(s: &str) -> String {
+	s.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+		.replace('\n', "\\n")
+}
+
+/// Format a sample value, spelling out values which aren't finite the way
+/// Prometheus expects.
+fn format_value(value: f64) -> String {
+	if value.is_nan() {
+		"NaN".to_string()
+	} else if value == f64::INFINITY {
+		"+Inf".to_string()
+	} else if value == f64::NEG_INFINITY {
+		"-Inf".to_string()
+	} else {
+		value.to_string()
+	}
+}
+
+/// Replace any characters which aren't valid in a metric name.
+fn sanitise(s: &str) -> String {
+	s.chars()
+		.map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+		.collect()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn encode_values() {
+		let mut store = BTreeMap::new();
+		let mut values = Values::new();
+		values.insert("usage".to_string(), Value::Number(12.5));
+		values.insert("status".to_string(), Value::from("Charging"));
+		store.insert("cpu-1".to_string(), values);
+		let mut values = Values::new();
+		values.insert("usage".to_string(), Value::Number(f64::INFINITY));
+		values.insert("rate".to_string(), Value::Number(f64::NAN));
+		store.insert("my \"disk\"\\\n".to_string(), values);
+
+		assert_eq!(
+			encode(&store),
+			"# TYPE rs_blocks_rate gauge\n\
+			rs_blocks_rate{block=\"my \\\"disk\\\"\\\\\\n\"} NaN\n\
+			# TYPE rs_blocks_usage gauge\n\
+			rs_blocks_usage{block=\"cpu-1\"} 12.5\n\
+			rs_blocks_usage{block=\"my \\\"disk\\\"\\\\\\n\"} +Inf\n"
+		);
+	}
+}
@@ -0,0 +1,42 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! Outputs which publish the messages received from blocks.
+
+use std::io;
+
+pub mod i3bar;
+pub mod jsonl;
+pub mod metrics;
+pub mod preview;
+pub mod waybar;
+
+/// Run an output's publishing loop, treating stdout having been closed (e.g.
+/// by the bar exiting) as publishing having finished rather than an error, so
+/// that shutdown actions still run.
+fn until_closed(publish: impl FnOnce() -> anyhow::Result<()>) -> anyhow::Result<()> {
+	match publish() {
+		Err(e)
+			if e.downcast_ref::<io::Error>()
+				.is_some_and(|x| x.kind() == io::ErrorKind::BrokenPipe) =>
+		{
+			Ok(())
+		}
+		result => result,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn closed_output() {
+		let closed = || Err(io::Error::from(io::ErrorKind::BrokenPipe).into());
+		assert!(until_closed(closed).is_ok());
+		let failed = || Err(io::Error::from(io::ErrorKind::PermissionDenied).into());
+		assert!(until_closed(failed).is_err());
+	}
+}
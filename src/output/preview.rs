@@ -0,0 +1,357 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! A live preview of the bar in the terminal, useful when developing configs.
+//!
+//! Pango colours and bold text are converted to ANSI escape codes, and each
+//! block is prefixed with its index. Pressing a block's index (`0` for the
+//! tenth) simulates a left click on that block, and pressing `m`, `r`, `u` or
+//! `d` beforehand simulates a middle click, right click, scroll up or scroll
+//! down respectively (e.g. `r2` right clicks the second block). The terminal is
+//! put into non-canonical mode so keys don't need to be followed by enter.
+//!
+//! A warning is printed for any block which stops updating or never updates.
+
+use crate::blocks::Message;
+use crate::click::{self, Click};
+use crate::shutdown;
+use crossbeam_channel::RecvTimeoutError;
+use regex::Regex;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const REFRESH: Duration = Duration::from_secs(1);
+const MIN_STALE: Duration = Duration::from_secs(10);
+
+struct Entry {
+	text: Option<String>,
+	updated: Instant,
+	longest_gap: Duration,
+	warned: bool,
+}
+
+impl Entry {
+	/// Create an entry for a block which hasn't sent any updates yet.
+	fn new() -> Entry {
+		Entry {
+			text: None,
+			updated: Instant::now(),
+			longest_gap: Duration::from_secs(0),
+			warned: false,
+		}
+	}
+
+	fn update(&mut self, text: String) {
+		if self.text.is_some() {
+			self.longest_gap = self.longest_gap.max(self.updated.elapsed());
+		}
+		self.updated = Instant::now();
+		self.text = Some(text);
+		self.warned = false;
+	}
+
+	/// A block is considered stale if it hasn't updated for much longer than it
+	/// usually takes.
+	fn is_stale(&self) -> bool {
+		self.updated.elapsed() > MIN_STALE.max(self.longest_gap * 3)
+	}
+}
+
+/// Render all blocks received on `r` in the terminal, with blocks appearing in
+/// the given order.
+pub fn publish(r: crossbeam_channel::Receiver<Message>, order: &[String]) -> anyhow::Result<()> {
+	let mut entries: HashMap<String, Entry> =
+		order.iter().map(|x| (x.clone(), Entry::new())).collect();
+	let _terminal = Terminal::non_canonical();
+	read_keys(order.to_vec());
+
+	loop {
+		match r.recv_timeout(REFRESH) {
+			Ok(message) => {
+				let block = message.block;
+				let text = pango_to_ansi(&block.full_text.unwrap_or_default());
+				entries
+					.entry(block.name)
+					.or_insert_with(Entry::new)
+					.update(text);
+			}
+			Err(RecvTimeoutError::Timeout) => {}
+			Err(RecvTimeoutError::Disconnected) => return Ok(()),
+		}
+
+		for name in order.iter() {
+			if let Some(entry) = entries.get_mut(name) {
+				if entry.is_stale() && !entry.warned {
+					let problem = if entry.text.is_some() {
+						"has not updated"
+					} else {
+						"has not sent any updates"
+					};
+					println!(
+						"\r\x1b[2K\x1b[33mwarning:\x1b[0m block '{}' {} for {}s",
+						name,
+						problem,
+						entry.updated.elapsed().as_secs()
+					);
+					entry.warned = true;
+				}
+			}
+		}
+		print_blocks(&entries, order);
+	}
+}
+
+/// Overwrite the current terminal line with all blocks.
+fn print_blocks(entries: &HashMap<String, Entry>, order: &[String]) {
+	let blocks: Vec<String> = order
+		.iter()
+		.enumerate()
+		.filter_map(|(i, name)| {
+			let text = entries.get(name)?.text.as_ref()?;
+			Some(format!("\x1b[2m{}:\x1b[0m {}", i + 1, text))
+		})
+		.collect();
+	print!("\r\x1b[2K{}", blocks.join(" \x1b[2m│\x1b[0m "));
+	io::stdout().flush().unwrap();
+}
+
+/// Terminal settings which are restored when dropped.
+struct Terminal(libc::termios);
+
+impl Terminal {
+	/// Stop the terminal on stdin from buffering input until enter is pressed
+	/// and from echoing it. The original settings are also restored on shutdown,
+	/// e.g. if the process is interrupted. Does nothing if stdin isn't a
+	/// terminal.
+	fn non_canonical() -> Option<Terminal> {
+		let mut original: libc::termios = unsafe { std::mem::zeroed() };
+		if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+			return None;
+		}
+		let mut settings = original;
+		settings.c_lflag &= !(libc::ICANON | libc::ECHO);
+		settings.c_cc[libc::VMIN] = 1;
+		settings.c_cc[libc::VTIME] = 0;
+		if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &settings) } != 0 {
+			return None;
+		}
+
+		shutdown::register(move || {
+			restore(&original);
+			println!();
+		});
+		Some(Terminal(original))
+	}
+}
+
+impl Drop for Terminal {
+	fn drop(&mut self) {
+		restore(&self.0);
+	}
+}
+
+fn restore(settings: &libc::termios) {
+	unsafe {
+		libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, settings);
+	}
+}
+
+/// Read keys from stdin as they're pressed and dispatch them as clicks on the
+/// blocks in `order`.
+fn read_keys(order: Vec<String>) {
+	thread::spawn(move || {
+		let mut keys = Keys::default();
+		for byte in io::stdin().lock().bytes() {
+			match keys.press(byte.unwrap() as char, &order) {
+				Ok(Some(click)) => click::dispatch(click),
+				Ok(None) => {}
+				Err(()) => println!(
+					"\r\x1b[2KUnrecognised key, expected a block's index optionally preceded \
+					by one of 'l', 'm', 'r', 'u' or 'd'"
+				),
+			}
+		}
+	});
+}
+
+/// Converts keypresses such as `r` followed by `2` into a click on a block.
+struct Keys {
+	button: u8,
+}
+
+impl Default for Keys {
+	fn default() -> Self {
+		Keys {
+			button: click::LEFT,
+		}
+	}
+}
+
+impl Keys {
+	/// Press a key, getting a click once a block's index is pressed. Keys which
+	/// don't choose a button or a block are an error.
+	fn press(&mut self, key: char, order: &[String]) -> Result<Option<Click>, ()> {
+		let button = match key {
+			'l' => click::LEFT,
+			'm' => click::MIDDLE,
+			'r' => click::RIGHT,
+			'u' => click::SCROLL_UP,
+			'd' => click::SCROLL_DOWN,
+			key if key.is_whitespace() => return Ok(None),
+			key => {
+				let index = match key.to_digit(10).ok_or(())? {
+					0 => 10,
+					x => x as usize,
+				};
+				let name = order.get(index - 1).ok_or(())?.clone();
+				let button = std::mem::replace(&mut self.button, click::LEFT);
+				return Ok(Some(Click { name, button }));
+			}
+		};
+		self.button = button;
+		Ok(None)
+	}
+}
+
+#[derive(Clone, Copy, Default)]
+struct Style {
+	bold: bool,
+	foreground: Option<(u8, u8, u8)>,
+	background: Option<(u8, u8, u8)>,
+}
+
+impl Style {
+	fn escape(&self) -> String {
+		let mut s = "\x1b[0".to_string();
+		if self.bold {
+			s.push_str(";1");
+		}
+		if let Some((r, g, b)) = self.foreground {
+			s.push_str(&format!(";38;2;{};{};{}", r, g, b));
+		}
+		if let Some((r, g, b)) = self.background {
+			s.push_str(&format!(";48;2;{};{};{}", r, g, b));
+		}
+		s.push('m');
+		s
+	}
+
+	/// Combine this style with the attributes of a pango tag.
+	fn with_tag(mut self, tag: &str) -> Style {
+		lazy_static! {
+			static ref RE: Regex = Regex::new(r#"(\w+)\s*=\s*['"]([^'"]*)['"]"#).unwrap();
+		}
+		if tag == "b" {
+			self.bold = true;
+		}
+		for caps in RE.captures_iter(tag) {
+			match (&caps[1], &caps[2]) {
+				("foreground", value) | ("fgcolor", value) | ("color", value) => {
+					self.foreground = parse_colour(value).or(self.foreground);
+				}
+				("background", value) | ("bgcolor", value) => {
+					self.background = parse_colour(value).or(self.background);
+				}
+				("weight", "bold") => self.bold = true,
+				_ => {}
+			}
+		}
+		self
+	}
+}
+
+/// Parse a colour of the form `#rrggbb`.
+fn parse_colour(s: &str) -> Option<(u8, u8, u8)> {
+	let s = s.strip_prefix('#')?;
+	if s.len() != 6 {
+		return None;
+	}
+	let channel = |i: usize| u8::from_str_radix(s.get(i..i + 2)?, 16).ok();
+	Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Convert pango markup into text containing ANSI escape codes.
+fn pango_to_ansi(s: &str) -> String {
+	let mut stack = vec![Style::default()];
+	let mut out = String::new();
+	let mut rest = s;
+
+	while let Some(start) = rest.find('<') {
+		out.push_str(&unescape(&rest[..start]));
+		let end = match rest[start..].find('>') {
+			Some(end) => start + end,
+			None => break,
+		};
+		let tag = rest[start + 1..end].trim();
+		if tag.starts_with('/') {
+			if stack.len() > 1 {
+				stack.pop();
+			}
+		} else {
+			stack.push(stack.last().unwrap().with_tag(tag));
+		}
+		out.push_str(&stack.last().unwrap().escape());
+		rest = &rest[end + 1..];
+	}
+	out.push_str(&unescape(rest));
+	out
+}
+
+/// Replace pango's character entities with the characters they represent.
+fn unescape(s: &str) -> String {
+	s.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn convert_pango() {
+		assert_eq!(
+			pango_to_ansi("<span foreground='#00ff00'>a</span> <b>b&amp;c</b>"),
+			"\x1b[0;38;2;0;255;0ma\x1b[0m \x1b[0;1mb&c\x1b[0m"
+		);
+		assert_eq!(pango_to_ansi("plain"), "plain");
+	}
+
+	#[test]
+	fn keys_to_clicks() {
+		let order = vec!["cpu".to_string(), "battery".to_string()];
+		let mut keys = Keys::default();
+		assert_eq!(keys.press('r', &order), Ok(None));
+		assert_eq!(
+			keys.press('2', &order),
+			Ok(Some(Click {
+				name: "battery".to_string(),
+				button: click::RIGHT
+			}))
+		);
+		assert_eq!(
+			keys.press('1', &order).unwrap().unwrap().button,
+			click::LEFT
+		);
+		assert_eq!(keys.press('\n', &order), Ok(None));
+		assert_eq!(keys.press('3', &order), Err(()));
+		assert_eq!(keys.press('0', &order), Err(()));
+		assert_eq!(keys.press('x', &order), Err(()));
+	}
+
+	#[test]
+	fn stale_entries() {
+		let mut entry = Entry::new();
+		entry.updated -= MIN_STALE * 2;
+		assert!(entry.is_stale());
+		entry.update("text".to_string());
+		assert!(!entry.is_stale());
+		assert_eq!(entry.longest_gap, Duration::from_secs(0));
+	}
+}
@@ -0,0 +1,76 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! Output for a waybar `custom` module.
+//!
+//! Waybar expects one JSON object per line, so only a single block should be
+//! sending messages to this output. A typical waybar config looks like:
+//!
+//! ```json
+//! "custom/battery": {
+//!     "exec": "rs-blocks --waybar battery",
+//!     "return-type": "json"
+//! }
+//! ```
+//!
+//! The block's state (if any) is given as the `class`, so it can be styled in
+//! waybar's stylesheet with e.g. `#custom-battery.critical`.
+
+use crate::blocks::{Message, State};
+use serde::Serialize;
+use std::io::{self, Write};
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Output {
+	text: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	class: Option<State>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	percentage: Option<u8>,
+}
+
+impl From<Message> for Output {
+	fn from(message: Message) -> Self {
+		Output {
+			text: message.block.full_text.unwrap_or_default(),
+			class: message.state,
+			percentage: message
+				.percentage
+				.map(|x| x.round().clamp(0.0, 100.0) as u8),
+		}
+	}
+}
+
+/// Publish each block received on `r` as a line of waybar JSON.
+pub fn publish(r: crossbeam_channel::Receiver<Message>) -> anyhow::Result<()> {
+	let stdout = io::stdout();
+	super::until_closed(|| {
+		for message in r.iter() {
+			let line = serde_json::to_string(&Output::from(message))?;
+			writeln!(stdout.lock(), "{}", line)?;
+		}
+		Ok(())
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::blocks::Block;
+
+	#[test]
+	fn block_to_output() {
+		let mut block = Block::new("battery".to_string(), true);
+		block.full_text = Some("50% (1h00m)".to_string());
+		let mut message = Message::new(block);
+		message.state = Some(State::Warning);
+		message.percentage = Some(49.6);
+
+		assert_eq!(
+			serde_json::to_string(&Output::from(message)).unwrap(),
+			r#"{"text":"50% (1h00m)","class":"warning","percentage":50}"#
+		);
+	}
+}
@@ -0,0 +1,56 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! Pressure stall information (PSI).
+//!
+//! The kernel reports the share of time in which some (or all) tasks were
+//! stalled waiting on the CPU, memory or IO in `/proc/pressure`. This is a
+//! better indication of whether a machine is struggling than its usage.
+
+use crate::blocks::Values;
+use std::fs;
+
+const PRESSURE_DIR: &str = "/proc/pressure";
+const RESOURCES: [&str; 3] = ["cpu", "memory", "io"];
+
+/// Insert the `some` and `full` avg10 pressure of each resource as percentages,
+/// e.g. `cpu_some` or `io_full`. Resources which can't be read (e.g. on kernels
+/// without PSI) are skipped.
+pub fn insert_values(values: &mut Values) {
+	for resource in RESOURCES.iter() {
+		let path = format!("{}/{}", PRESSURE_DIR, resource);
+		if let Ok(contents) = fs::read_to_string(path) {
+			for (kind, avg10) in parse(&contents) {
+				values.insert(format!("{}_{}", resource, kind), avg10.into());
+			}
+		}
+	}
+}
+
+/// Parse the avg10 value of each line of a pressure file, e.g.
+/// `some avg10=1.23 avg60=0.50 avg300=0.10 total=12345`.
+fn parse(contents: &str) -> Vec<(&str, f32)> {
+	contents
+		.lines()
+		.filter_map(|line| {
+			let mut fields = line.split_whitespace();
+			let kind = fields.next()?;
+			let avg10 = fields.find_map(|x| x.strip_prefix("avg10="))?;
+			Some((kind, avg10.parse().ok()?))
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parse_pressure() {
+		let contents = "some avg10=8.64 avg60=5.01 avg300=3.27 total=56181071\n\
+			full avg10=0.14 avg60=0.00 avg300=0.00 total=0\n";
+		assert_eq!(parse(contents), [("some", 8.64), ("full", 0.14)]);
+	}
+}
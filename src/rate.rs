@@ -0,0 +1,64 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! Rates of change of counters, such as the bytes sent over a network interface
+//! or read from a disk.
+
+use crate::ema;
+use std::time::Instant;
+
+/// The rate of change of a counter, smoothed with an exponential moving
+/// average.
+pub struct Speed {
+	prev: Option<(u64, Instant)>,
+	ema: ema::Ema<f64>,
+}
+
+impl Speed {
+	pub fn new(alpha: f64) -> Speed {
+		Speed {
+			prev: None,
+			ema: ema::Ema::new(alpha),
+		}
+	}
+
+	/// Push a reading of the counter taken at `now` and get the change since the
+	/// last reading along with the smoothed rate per second. There's no rate for
+	/// the first reading or if the counter has gone backwards (e.g. it was reset
+	/// when an interface went down).
+	pub fn push(&mut self, value: u64, now: Instant) -> Option<(u64, f64)> {
+		let (prev, then) = self.prev.replace((value, now))?;
+		let elapsed = now.duration_since(then).as_secs_f64();
+		if value < prev || elapsed <= 0.0 {
+			self.ema.reset();
+			return None;
+		}
+		let delta = value - prev;
+		Some((delta, self.ema.push(delta as f64 / elapsed)))
+	}
+
+	pub fn reset(&mut self) {
+		self.prev = None;
+		self.ema.reset();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::time::Duration;
+
+	#[test]
+	fn test_speed() {
+		let mut speed = Speed::new(0.5);
+		let now = Instant::now();
+		let later = |secs| now + Duration::from_secs(secs);
+		assert_eq!(speed.push(u64::MAX - 3000, now), None);
+		assert_eq!(speed.push(u64::MAX - 1000, later(2)), Some((2000, 1000.0)));
+		assert_eq!(speed.push(u64::MAX, later(3)), Some((1000, 1000.0)));
+		assert_eq!(speed.push(500, later(4)), None);
+		assert_eq!(speed.push(4500, later(6)), Some((4000, 2000.0)));
+	}
+}
@@ -0,0 +1,68 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! Actions to run when rs-blocks shuts down, either because its output closed
+//! or because it was interrupted or terminated, e.g. saving state which is
+//! otherwise only saved periodically.
+
+use signal_hook::iterator::Signals;
+use std::process;
+use std::sync::Mutex;
+use std::thread;
+
+type Action = Box<dyn FnOnce() + Send>;
+
+lazy_static! {
+	static ref ACTIONS: Mutex<Vec<Action>> = Mutex::new(Vec::new());
+}
+
+/// Register an action to run on shutdown.
+pub fn register(action: impl FnOnce() + Send + 'static) {
+	ACTIONS.lock().unwrap().push(Box::new(action));
+}
+
+/// Run all registered actions, in the order they were registered.
+pub fn run() {
+	let actions = std::mem::take(&mut *ACTIONS.lock().unwrap());
+	for action in actions {
+		action();
+	}
+}
+
+/// Run all registered actions and exit when interrupted, terminated or hung
+/// up on.
+pub fn on_signals() -> anyhow::Result<()> {
+	let signals = Signals::new([
+		signal_hook::SIGINT,
+		signal_hook::SIGTERM,
+		signal_hook::SIGHUP,
+	])?;
+	thread::spawn(move || {
+		if let Some(signal) = signals.forever().next() {
+			run();
+			process::exit(128 + signal);
+		}
+	});
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::sync::Arc;
+
+	#[test]
+	fn run_actions_once() {
+		let count = Arc::new(AtomicUsize::new(0));
+		let count2 = count.clone();
+		register(move || {
+			count2.fetch_add(1, Ordering::SeqCst);
+		});
+		run();
+		run();
+		assert_eq!(count.load(Ordering::SeqCst), 1);
+	}
+}
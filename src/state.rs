@@ -0,0 +1,56 @@
+// Copyright ⓒ 2019-2021 Lewis Belcher
+// Licensed under the MIT license (see LICENSE or <http://opensource.org/licenses/MIT>).
+// All files in the project carrying such notice may not be copied, modified, or
+// distributed except according to those terms
+
+//! State which blocks persist across restarts.
+//!
+//! Each block's state is kept as JSON in a file named after the block in
+//! `$XDG_STATE_HOME/rs-blocks/` (usually `~/.local/state/rs-blocks/`).
+
+use anyhow::Context;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Get the directory state files are kept in.
+fn dir() -> Option<PathBuf> {
+	let base = env::var_os("XDG_STATE_HOME")
+		.map(PathBuf::from)
+		.filter(|x| x.is_absolute())
+		.or_else(|| dirs::home_dir().map(|x| x.join(".local/state")))?;
+	Some(base.join("rs-blocks"))
+}
+
+/// Get the path of the state file for the block with the given name.
+fn path(name: &str) -> Option<PathBuf> {
+	dir().map(|x| x.join(format!("{}.json", name)))
+}
+
+/// Load the state of the block with the given name. Missing or unreadable state
+/// is treated as no state.
+pub fn load<T: DeserializeOwned>(name: &str) -> Option<T> {
+	let path = path(name)?;
+	let contents = fs::read_to_string(&path).ok()?;
+	match serde_json::from_str(&contents) {
+		Ok(state) => Some(state),
+		Err(e) => {
+			log::warn!("Ignoring bad state in '{}': {}", path.display(), e);
+			None
+		}
+	}
+}
+
+/// Save the state of the block with the given name. The file is replaced
+/// atomically so a crash never leaves it half written.
+pub fn save<T: Serialize>(name: &str, state: &T) -> anyhow::Result<()> {
+	let path = path(name).context("Could not determine state directory")?;
+	let dir = path.parent().unwrap();
+	fs::create_dir_all(dir).context(format!("Failed to create '{}'", dir.display()))?;
+	let tmp = path.with_extension("tmp");
+	fs::write(&tmp, serde_json::to_string(state)?)
+		.context(format!("Failed to write '{}'", tmp.display()))?;
+	fs::rename(&tmp, &path).context(format!("Failed to write '{}'", path.display()))
+}
@@ -3,6 +3,9 @@
 // All files in the project carrying such notice may not be copied, modified, or
 // distributed except according to those terms
 
+use crate::blocks::{State, Value, Values};
+use regex::Regex;
+use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::io::{self, Read, Seek, SeekFrom};
 use std::num;
@@ -11,9 +14,9 @@ use std::thread;
 use std::time::Duration;
 
 /// Seek to the beginning of a file and read all its contents into a string.
-fn read_to_string(f: &mut File, mut buf: &mut String) -> io::Result<()> {
+fn read_to_string(f: &mut File, buf: &mut String) -> io::Result<()> {
 	f.seek(SeekFrom::Start(0))?;
-	f.read_to_string(&mut buf)?;
+	f.read_to_string(buf)?;
 	Ok(())
 }
 
@@ -27,6 +30,78 @@ pub fn file_to_f32(path: &str) -> Result<f32, num::ParseFloatError> {
 	fs::read_to_string(path).unwrap().trim().parse()
 }
 
+/// Replace placeholders of the form `{key}` in `format` with the corresponding
+/// value in `values`. Numbers are rounded to the nearest integer unless a
+/// precision is given, e.g. `{key:.1}`. Unknown placeholders are removed.
+pub fn format(format: &str, values: &Values) -> String {
+	lazy_static! {
+		static ref RE: Regex = Regex::new(r"\{(\w+)(?::\.(\d+))?\}").unwrap();
+	}
+	RE.replace_all(format, |caps: &regex::Captures| {
+		match values.get(&caps[1]) {
+			Some(Value::Number(x)) => {
+				let precision = caps.get(2).map_or(0, |p| p.as_str().parse().unwrap());
+				format!("{:.*}", precision, x)
+			}
+			Some(Value::Text(x)) => x.clone(),
+			None => String::new(),
+		}
+	})
+	.to_string()
+}
+
+/// Thresholds on a block's values, keyed by the name of the value.
+pub type Thresholds = BTreeMap<String, f64>;
+
+/// Get the state of a block from its values. The block is critical (or a
+/// warning) when any value reaches its critical (or warning) threshold.
+pub fn threshold_state(
+	values: &Values,
+	warning: &Thresholds,
+	critical: &Thresholds,
+) -> Option<State> {
+	let reached = |thresholds: &Thresholds| {
+		thresholds.iter().any(
+			|(key, threshold)| matches!(values.get(key), Some(Value::Number(x)) if x >= threshold),
+		)
+	};
+	if reached(critical) {
+		Some(State::Critical)
+	} else if reached(warning) {
+		Some(State::Warning)
+	} else {
+		None
+	}
+}
+
+/// Format a number of bytes in binary units, e.g. `1.5GiB`.
+pub fn human_bytes(bytes: f64) -> String {
+	const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+	if bytes < 1024.0 {
+		return format!("{:.0}B", bytes);
+	}
+	let mut value = bytes / 1024.0;
+	let mut unit = 0;
+	while value >= 1024.0 && unit < UNITS.len() - 1 {
+		value /= 1024.0;
+		unit += 1;
+	}
+	format!("{:.1}{}", value, UNITS[unit])
+}
+
+/// Get placeholders for `values` with every number of bytes whose key ends in
+/// `suffix` also given in human readable units under the key without the suffix,
+/// e.g. `used` for `used_bytes` when the suffix is `_bytes`.
+pub fn human_placeholders(values: &Values, suffix: &str) -> Values {
+	let mut placeholders = values.clone();
+	for (key, value) in values.iter() {
+		if let (Some(key), Value::Number(bytes)) = (key.strip_suffix(suffix), value) {
+			placeholders.insert(key.to_string(), human_bytes(*bytes).into());
+		}
+	}
+	placeholders
+}
+
 /// A monitoring abstraction which will periodically call `reader` when iterated.
 pub struct Monitor<T>
 where
@@ -79,7 +154,7 @@ pub fn monitor_file(path: String, period: f32) -> Monitor<impl FnMut() -> String
 	Monitor::new(
 		move || {
 			buf.truncate(0);
-			if let Ok(_) = read_to_string(&mut file, &mut buf) {
+			if read_to_string(&mut file, &mut buf).is_ok() {
 				buf.clone()
 			} else {
 				format!("Failed to read: {}", &path)
@@ -122,3 +197,59 @@ pub fn wait_for_signal(signal: i32, timeout: f32) -> crossbeam_channel::Receiver
 	});
 	r
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn format_placeholders() {
+		let mut values = Values::new();
+		values.insert("usage".to_string(), Value::Number(12.345));
+		values.insert("status".to_string(), Value::from("Full"));
+		assert_eq!(
+			format("{usage} {usage:.2} {status}{missing}", &values),
+			"12 12.35 Full"
+		);
+	}
+
+	#[test]
+	fn thresholds() {
+		let mut values = Values::new();
+		values.insert("usage".to_string(), Value::Number(50.0));
+		values.insert("cpu_some".to_string(), Value::Number(20.0));
+		let warning: Thresholds = vec![("usage".to_string(), 50.0)].into_iter().collect();
+		let critical: Thresholds = vec![("cpu_some".to_string(), 30.0)].into_iter().collect();
+		assert_eq!(
+			threshold_state(&values, &warning, &critical),
+			Some(State::Warning)
+		);
+		values.insert("cpu_some".to_string(), Value::Number(30.0));
+		assert_eq!(
+			threshold_state(&values, &warning, &critical),
+			Some(State::Critical)
+		);
+		let none = Thresholds::new();
+		assert_eq!(threshold_state(&values, &none, &none), None);
+	}
+
+	#[test]
+	fn human_bytes_units() {
+		assert_eq!(human_bytes(512.0), "512B");
+		assert_eq!(human_bytes(1536.0), "1.5KiB");
+		assert_eq!(human_bytes(3.0 * 1024.0 * 1024.0 * 1024.0), "3.0GiB");
+	}
+
+	#[test]
+	fn human_placeholders_suffix() {
+		let mut values = Values::new();
+		values.insert("used_bytes".to_string(), Value::Number(1536.0));
+		values.insert("read_bytes_per_sec".to_string(), Value::Number(512.0));
+		let placeholders = human_placeholders(&values, "_bytes");
+		assert_eq!(placeholders["used"], Value::from("1.5KiB"));
+		assert_eq!(placeholders["used_bytes"], Value::Number(1536.0));
+		assert!(!placeholders.contains_key("read"));
+		let placeholders = human_placeholders(&values, "_bytes_per_sec");
+		assert_eq!(placeholders["read"], Value::from("512B"));
+	}
+}